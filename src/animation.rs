@@ -0,0 +1,19 @@
+use crate::{render::RenderTarget, scene::Scene, timeline::Timeline};
+
+#[derive(Default)]
+pub struct Animation {
+    pub scene: Scene,
+    pub unlit: bool, // Draw flat colours instead of lighting the scene
+    pub timeline: Timeline,
+    pub render_target: RenderTarget,
+}
+
+impl Animation {
+    /// Pose the models as they are `time` seconds after the start of the
+    /// timeline. Only depends on the time, not on earlier updates.
+    pub fn update(&mut self, time: f32) {
+        for model in self.scene.models.iter_mut() {
+            self.timeline.apply(&model.name, &mut model.transform, time);
+        }
+    }
+}
@@ -1,38 +1,116 @@
+use crate::gltf::Gltf;
 use crate::render::Model;
-use crate::obj::Obj;
+use crate::obj::{Obj, ObjError, ParseMode};
+use crate::ply::Ply;
+use crate::stl::Stl;
+use crate::triangulate::Triangulation;
+
+/// Settings for how the assets are read and converted into models.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LoadOptions {
+    pub parse_mode: ParseMode,
+    pub triangulation: Triangulation, // Fan unless ear clipping is asked for
+}
+
+/// The models loaded from a file, whole and split into the objects and groups
+/// it is made of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asset {
+    pub name: String, // The file name without the extension
+    pub whole: Vec<Model>, // Everything in the file as a single model, or every mesh placed in a glTF scene
+    pub parts: Vec<Model>, // One model for each object or group in the file, or mesh in a glTF scene
+}
+
+impl Asset {
+    /// The models to draw for this asset, either its parts or the whole file.
+    pub fn models(&self, parts: bool) -> Vec<Model> {
+        if parts { self.parts.clone() } else { self.whole.clone() }
+    }
+}
 
 #[derive(Default)]
 pub struct AssetLoader {
-    models: Vec<Model>,
+    assets: Vec<Asset>,
 }
 
 impl AssetLoader {
-    pub fn new() -> Self {
+    /// Load every model in the assets directory. Files that fail to load are
+    /// reported and left out.
+    pub fn new(options: LoadOptions) -> Self {
         let mut asset_loader = AssetLoader {
-            models: Vec::new(),
+            assets: Vec::new(),
         };
-        asset_loader.initialize();
+        asset_loader.initialize(options);
         asset_loader
     }
 
-    fn initialize(&mut self) {
-        self.models.clear();
+    fn initialize(&mut self, options: LoadOptions) {
+        self.assets.clear();
         let assets_dir = "assets";
         for entry in std::fs::read_dir(assets_dir)
-            .expect(format!("Failed to read assets directory {:?}", &assets_dir).as_str()) {
+            .unwrap_or_else(|e| panic!("Failed to read assets directory {:?}: {}", &assets_dir, e)) {
             let entry = entry.expect("Failed to read entry in assets directory");
-            if entry.path().extension().map_or(false, |ext| ext == "obj") {
-                let obj = Obj::read_from_file(
-                    entry.path()
-                        .to_str().expect("Failed to convert path to str"))
-                        .expect("Failed to read OBJ file");
-                let model = Model::from(obj);
-                self.models.push(model);
+            let path = entry.path();
+            let Some(extension) = path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase()) else {
+                continue;
+            };
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let path = path.to_string_lossy().to_string();
+            let asset = match extension.as_str() {
+                "obj" => Self::load_obj(&path, options).map_err(|e| format!("Failed to read OBJ file: {}", e)),
+                "stl" => Self::load_stl(&path).map_err(|e| format!("Failed to read STL file: {}", e)),
+                "ply" => Self::load_ply(&path, options).map_err(|e| format!("Failed to read PLY file: {}", e)),
+                "gltf" | "glb" => Self::load_gltf(&path).map_err(|e| format!("Failed to read glTF file: {}", e)),
+                _ => continue,
+            };
+            match asset {
+                Ok(mut asset) => {
+                    // A single model of the whole file is named after it
+                    if let [model] = asset.whole.as_mut_slice() {
+                        model.name = name.clone();
+                    }
+                    asset.name = name;
+                    self.assets.push(asset);
+                },
+                Err(e) => eprintln!("{}", e),
             }
         }
     }
 
-    pub fn get_models(&self) -> &Vec<Model> {
-        &self.models
+    fn load_obj(path: &str, options: LoadOptions) -> Result<Asset, ObjError> {
+        Ok(Self::asset_from_obj(Obj::read_from_file(path, options.parse_mode)?, options))
+    }
+
+    fn load_ply(path: &str, options: LoadOptions) -> Result<Asset, String> {
+        let obj = Obj::from_ply(&Ply::read_from_file(path)?).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Self::asset_from_obj(obj, options))
     }
-}
\ No newline at end of file
+
+    fn asset_from_obj(mut obj: Obj, options: LoadOptions) -> Asset {
+        obj.generate_normals();
+        let parts = obj.objects.iter().map(|object| Model::from_object(&obj, object, options.triangulation)).collect();
+        let model = Model::from(obj, options.triangulation);
+        Asset { name: String::new(), whole: vec![model], parts }
+    }
+
+    fn load_stl(path: &str) -> Result<Asset, String> {
+        let model = Model::from_stl(&Stl::read_from_file(path)?);
+        Ok(Asset { name: String::new(), whole: vec![model.clone()], parts: vec![model] })
+    }
+
+    // Every mesh placed in the scene becomes a part, named after its node. The
+    // meshes keep their own places, so they make up the whole file as well.
+    fn load_gltf(path: &str) -> Result<Asset, String> {
+        let gltf = Gltf::read_from_file(path)?;
+        let parts: Vec<Model> = gltf.mesh_instances().iter().map(|instance| {
+            let mut part = Model::from_gltf(&gltf, instance);
+            part.name = gltf.instance_name(instance);
+            part
+        }).collect();
+        Ok(Asset { name: String::new(), whole: parts.clone(), parts })
+    }
+
+    pub fn get_assets(&self) -> &Vec<Asset> {
+        &self.assets
+    }
+}
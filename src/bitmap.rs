@@ -1,62 +1,102 @@
 use std::io::{BufWriter, Write};
 
-use crate::float3::{Float3};
+use crate::render::ImageView;
 
 
-pub fn write_image_to_file(image : &Vec<Vec<Float3>>, filename: &str) -> Result<(), std::io::Error> {
+pub fn write_image_to_file(image : &ImageView, filename: &str) -> Result<(), std::io::Error> {
     // Ensure the directory exists
     if let Some(dir) = std::path::Path::new(filename).parent() {
         std::fs::create_dir_all(dir)?;
     }
 
-    let width : usize = image[0].len();
-    let height : usize = image.len();
+    let width : usize = image.width;
+    let height : usize = image.height;
 
     // Open the file in write mode
     let mut file = BufWriter::new(std::fs::File::create(filename)?);
     
     // Write BMP header
-    file.write("BM".as_bytes())?;
+    file.write_all("BM".as_bytes())?;
     let file_size : u32 = 54 + width as u32 * height as u32 * 4; // 54 bytes for header + pixel data
-    file.write(&file_size.to_le_bytes())?;
+    file.write_all(&file_size.to_le_bytes())?;
     
     // Reserved bytes (set to 0)
-    file.write(&[0, 0, 0, 0])?;
+    file.write_all(&[0, 0, 0, 0])?;
     let pixel_data_offset : i32 = 54;
-    file.write(&pixel_data_offset.to_le_bytes())?;
+    file.write_all(&pixel_data_offset.to_le_bytes())?;
     let dib_header_size: i32 = 40;
-    file.write(&dib_header_size.to_le_bytes())?;
-    file.write(&(width as i32).to_le_bytes())?;
-    file.write(&(height as i32).to_le_bytes())?;
+    file.write_all(&dib_header_size.to_le_bytes())?;
+    file.write_all(&(width as i32).to_le_bytes())?;
+    file.write_all(&(height as i32).to_le_bytes())?;
     
     // Write color planes (always 1 for BMP)
-    file.write(&[1, 0])?;
+    file.write_all(&[1, 0])?;
     
     // Write bits per pixel (24 for RGB)
-    file.write(&[32, 0])?;
+    file.write_all(&[32, 0])?;
     
     // Write compression (0 for no compression)
-    file.write(&[0, 0, 0, 0])?;
+    file.write_all(&[0, 0, 0, 0])?;
     let pixel_data_size : u32 = width as u32 * height as u32 * 4; // 4 bytes per pixel for RGB plus padding
-    file.write(&pixel_data_size.to_le_bytes())?;
-    file.write(&[0, 0, 0, 0])?; // Horizontal resolution (pixels per meter)
-    file.write(&[0, 0, 0, 0])?; // Vertical resolution (pixels per meter)
-    file.write(&[0, 0, 0, 0])?; // Number of colors in the palette (0 for 24-bit BMP)
-    file.write(&[0, 0, 0, 0])?; // Important colors (0 means all colors are important)
+    file.write_all(&pixel_data_size.to_le_bytes())?;
+    file.write_all(&[0, 0, 0, 0])?; // Horizontal resolution (pixels per meter)
+    file.write_all(&[0, 0, 0, 0])?; // Vertical resolution (pixels per meter)
+    file.write_all(&[0, 0, 0, 0])?; // Number of colors in the palette (0 for 24-bit BMP)
+    file.write_all(&[0, 0, 0, 0])?; // Important colors (0 means all colors are important)
 
     // Write pixel data
-    for y in 0..height { 
-        for x in 0..width {
-            let pixel = image[y][x];
-            let r = (pixel.r() * 255.0) as u8;
-            let g = (pixel.g() * 255.0) as u8;
-            let b = (pixel.b() * 255.0) as u8;
-            file.write(&[b, g, r, 0])?; // Write pixel in BGR format with padding
+    for row in image.rows() { 
+        for &[r, g, b, _] in row {
+            file.write_all(&[b, g, r, 0])?; // Write pixel in BGR format with padding
         }
     }
 
     // Flush the file to ensure all data is written
     file.flush()?;
-    println!("Image written to {}", filename);
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Decode an uncompressed 24 or 32 bit BMP image.
+///
+/// Returns the width, height and the pixels as RGBA8, row by row from the top.
+pub fn decode_bmp(data: &[u8]) -> Result<(usize, usize, Vec<[u8; 4]>), String> {
+    if data.len() < 54 || &data[0..2] != "BM".as_bytes() {
+        return Err("Not a BMP file".to_string());
+    }
+
+    let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+    let pixel_data_offset = u32_at(10) as usize;
+    let width = u32_at(18) as i32;
+    let height = u32_at(22) as i32;
+    let bits_per_pixel = u16_at(28);
+    let compression = u32_at(30);
+
+    if width <= 0 || height == 0 {
+        return Err("Invalid image size".to_string());
+    }
+    // Compression 3 (bit fields) is accepted for 32 bit images in the usual BGRA layout
+    if bits_per_pixel != 24 && bits_per_pixel != 32 || (compression != 0 && compression != 3) {
+        return Err("Only uncompressed 24 and 32 bit images are supported".to_string());
+    }
+
+    let width = width as usize;
+    let bottom_up = height > 0; // Rows are stored bottom to top unless the height is negative
+    let height = height.unsigned_abs() as usize;
+    let bytes_per_pixel = bits_per_pixel as usize / 8;
+    let row_size = (width * bytes_per_pixel).div_ceil(4) * 4; // Rows are padded to 4 bytes
+    if data.len() < pixel_data_offset + row_size * height {
+        return Err("Pixel data is truncated".to_string());
+    }
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = if bottom_up { height - 1 - y } else { y };
+        let start = pixel_data_offset + row * row_size;
+        for pixel in data[start..start + width * bytes_per_pixel].chunks_exact(bytes_per_pixel) {
+            pixels.push([pixel[2], pixel[1], pixel[0], 255]);
+        }
+    }
+
+    Ok((width, height, pixels))
+}
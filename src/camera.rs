@@ -0,0 +1,79 @@
+use crate::{float3::Float3, float4::Float4, transform::Transform};
+
+/// The point of view a scene is rendered from, and how it is projected.
+///
+/// The camera looks along its local +Z axis with +Y up. Its orientation uses
+/// the same angles as a `Transform`, so a yaw turns it to the left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub position: Float3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub fov: f32, // Vertical field of view in degrees
+    pub near: f32, // Distance to the near clipping plane
+    pub far: f32, // Distance to the far clipping plane
+    pub aspect: Option<f32>, // Width divided by height, or that of the render target if not given
+}
+
+impl Default for Camera {
+    /// At the origin, looking along +Z.
+    fn default() -> Self {
+        Camera { position: Float3::zero(), yaw: 0.0, pitch: 0.0, roll: 0.0, fov: 60.0, near: 0.1, far: 1000.0, aspect: None }
+    }
+}
+
+impl Camera {
+    /// A camera at `position` that looks at `target`, without any roll.
+    pub fn looking_at(position: Float3, target: Float3) -> Self {
+        let mut camera = Camera { position, ..Camera::default() };
+        camera.look_at(target);
+        camera
+    }
+
+    /// Turn the camera towards a point, keeping +Y up. Looking straight up or
+    /// down keeps the current yaw.
+    pub fn look_at(&mut self, target: Float3) {
+        let forward = target - self.position;
+        let horizontal = (forward.x * forward.x + forward.z * forward.z).sqrt();
+        if horizontal > 0.0 {
+            self.yaw = f32::atan2(-forward.x, forward.z);
+        }
+        if forward.length() > 0.0 {
+            self.pitch = f32::atan2(forward.y, horizontal);
+        }
+        self.roll = 0.0;
+    }
+
+    // The transform that places the camera in the world
+    fn transform(&self) -> Transform {
+        Transform { yaw: self.yaw, pitch: self.pitch, roll: self.roll, position: self.position, ..Transform::default() }
+    }
+
+    /// The aspect ratio used for a render target of the given size.
+    pub fn aspect_for(&self, width: usize, height: usize) -> f32 {
+        self.aspect.unwrap_or(width as f32 / height as f32)
+    }
+
+    /// Move a world-space point into view space, where the camera sits at the
+    /// origin and looks along +Z.
+    pub fn world_to_view(&self, p: &Float3) -> Float3 {
+        self.transform().to_local_point(p)
+    }
+
+    /// Project a view-space point into homogeneous clip space.
+    ///
+    /// `w` holds the view-space depth and `z` is remapped so that `z / w` runs
+    /// from 0 at the near plane to 1 at the far plane.
+    pub fn view_to_clip(&self, p: &Float3, aspect: f32) -> Float4 {
+        let half_height_world : f32 = (self.fov.to_radians() / 2.0).tan();
+        let depth_scale = self.far / (self.far - self.near);
+
+        Float4::new(
+            p.x / (half_height_world * aspect),
+            p.y / half_height_world,
+            (p.z - self.near) * depth_scale,
+            p.z,
+        )
+    }
+}
@@ -0,0 +1,304 @@
+use std::error::Error;
+
+use crate::{
+    animation::Animation,
+    asset::{Asset, AssetLoader, LoadOptions},
+    bitmap,
+    obj::{Obj, ParseMode},
+    render::{RenderState, RenderTarget},
+    scene::Scene,
+    stl::{Stl, StlFormat},
+    timeline::Timeline,
+    y4m::Y4mWriter,
+};
+
+/// Options understood by both the viewer and the commands.
+#[derive(Debug, Clone, Default)]
+pub struct CommonOptions {
+    pub render_state: RenderState,
+    pub unlit: bool, // Draw flat colours instead of lighting the scene
+    pub load_options: LoadOptions,
+    pub parts: bool, // Load the objects and groups of the model as separate models
+    pub timeline: Option<String>, // File with the keyframes that animate the models
+}
+
+pub const COMMON_USAGE: &str = "[--cull none|back|front] [--front-face cw|ccw] [--unlit] [--strict] [--parts] [--triangulation fan|ear] [--timeline file]";
+
+impl CommonOptions {
+    /// Apply `option` if it is a common option, taking its value from `values`.
+    /// Returns whether the option was recognised.
+    pub fn parse<'a>(&mut self, option: &str, values: &mut impl Iterator<Item = &'a String>) -> Result<bool, Box<dyn Error>> {
+        match option {
+            "--cull" => self.render_state.cull_mode = values.next().ok_or("Missing cull mode")?.parse()?,
+            "--front-face" => self.render_state.front_face = values.next().ok_or("Missing front face")?.parse()?,
+            "--unlit" => self.unlit = true,
+            "--strict" => self.load_options.parse_mode = ParseMode::Strict,
+            "--triangulation" => self.load_options.triangulation = values.next().ok_or("Missing triangulation")?.parse()?,
+            "--parts" => self.parts = true,
+            "--timeline" => self.timeline = Some(values.next().ok_or("Missing timeline file")?.clone()),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The timeline given with `--timeline`, or the default one.
+    pub fn timeline(&self) -> Result<Timeline, String> {
+        match &self.timeline {
+            Some(path) => Timeline::read_from_file(path),
+            None => Ok(Timeline::default()),
+        }
+    }
+}
+
+/// Find a loaded asset by its file name without the extension, or by its number.
+pub fn select_asset<'a>(assets: &'a [Asset], model: &str) -> Result<&'a Asset, String> {
+    if let Some(asset) = assets.iter().find(|asset| asset.name == model) {
+        return Ok(asset);
+    }
+    match model.parse::<usize>() {
+        Ok(number) => assets.get(number)
+            .ok_or_else(|| format!("Model number {} is out of range. Available models: 0 to {}", number, assets.len().saturating_sub(1))),
+        Err(_) => {
+            let names: Vec<&str> = assets.iter().map(|asset| asset.name.as_str()).collect();
+            Err(format!("Unknown model '{}'. Available models: {}", model, names.join(", ")))
+        },
+    }
+}
+
+/// The path of one frame of several, with the frame number in front of the
+/// extension, e.g. `frame_0003.bmp`. A single frame keeps the path as given.
+pub fn frame_path(path: &str, frame: usize, frame_count: usize) -> String {
+    if frame_count <= 1 {
+        return path.to_string();
+    }
+    let path = std::path::Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}_{:04}.{}", stem, frame, extension.to_string_lossy()),
+        None => format!("{}_{:04}", stem, frame),
+    };
+    path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+// Tell where the images of a command went, once for all of them
+fn report_images(path: &str, frame_count: usize) {
+    match frame_count {
+        1 => println!("Image written to {}", path),
+        _ => println!("{} images written to {} to {}", frame_count, frame_path(path, 0, frame_count), frame_path(path, frame_count - 1, frame_count)),
+    }
+}
+
+/// Options of the commands that render without a window.
+struct OutputOptions {
+    model: Option<String>,
+    out: Option<String>,
+    width: usize,
+    height: usize,
+    distance: f32, // How far the camera is from the model
+    fov: Option<f32>,
+}
+
+const OUTPUT_USAGE: &str = "--model name|number --out file [--size 512x512] [--distance z] [--fov degrees]";
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions { model: None, out: None, width: 512, height: 512, distance: 5.0, fov: None }
+    }
+}
+
+impl OutputOptions {
+    // Like `CommonOptions::parse`
+    fn parse<'a>(&mut self, option: &str, values: &mut impl Iterator<Item = &'a String>) -> Result<bool, Box<dyn Error>> {
+        let mut value = || values.next().ok_or_else(|| format!("Missing value for {}", option));
+        match option {
+            "--model" => self.model = Some(value()?.clone()),
+            "--out" => self.out = Some(value()?.clone()),
+            "--size" => {
+                let size = value()?;
+                let parsed = size.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+                match parsed {
+                    Some((w, h)) if w > 0 && h > 0 => (self.width, self.height) = (w, h),
+                    _ => return Err(format!("Invalid size '{}', expected WIDTHxHEIGHT", size).into()),
+                }
+            },
+            "--distance" => self.distance = value()?.parse()?,
+            "--fov" => self.fov = Some(value()?.parse()?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // Load the selected model and point the camera at it, with the default lights
+    fn scene(&self, common: &CommonOptions) -> Result<Scene, Box<dyn Error>> {
+        let model = self.model.as_deref().ok_or("Missing --model")?;
+        let assets = AssetLoader::new(common.load_options);
+        let models = select_asset(assets.get_assets(), model)?.models(common.parts);
+        let mut camera = Scene::default_camera(self.distance);
+        if let Some(fov) = self.fov {
+            camera.fov = fov;
+        }
+        Ok(Scene { models, lights: Scene::default_lights(), camera })
+    }
+
+    fn render_target(&self, common: &CommonOptions) -> RenderTarget {
+        let mut render_target = RenderTarget::new(self.width, self.height);
+        render_target.render_state = common.render_state;
+        render_target
+    }
+}
+
+pub fn render_usage() -> String {
+    format!("render {} [--yaw degrees[,degrees...]] [--pitch degrees]", OUTPUT_USAGE)
+}
+
+/// Render still frames of a model without opening a window and write them to
+/// BMP files. Every angle given to `--yaw` gives a frame of its own.
+pub fn render_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut common = CommonOptions::default();
+    let mut output = OutputOptions::default();
+    let mut yaws = vec![0.0];
+    let mut pitch = 0.0;
+
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        if common.parse(option, &mut options)? || output.parse(option, &mut options)? {
+            continue;
+        }
+        let mut value = || options.next().ok_or_else(|| format!("Missing value for {}", option));
+        match option.as_str() {
+            "--yaw" => yaws = value()?.split(',').map(str::parse).collect::<Result<_, _>>()?,
+            "--pitch" => pitch = value()?.parse()?,
+            _ => return Err(format!("Unknown option '{}'", option).into()),
+        }
+    }
+    let out = output.out.as_deref().ok_or("Missing --out")?;
+    let mut scene = output.scene(&common)?;
+    let mut render_target = output.render_target(&common);
+
+    for (frame, &yaw) in yaws.iter().enumerate() {
+        for model in scene.models.iter_mut() {
+            model.transform.yaw = f32::to_radians(yaw);
+            model.transform.pitch = f32::to_radians(pitch);
+        }
+        scene.draw(&mut render_target, common.unlit);
+        bitmap::write_image_to_file(&render_target.view(), &frame_path(out, frame, yaws.len()))?;
+    }
+    report_images(out, yaws.len());
+    Ok(())
+}
+
+pub fn export_usage() -> String {
+    format!("export {} [--fps 30] [--duration seconds]", OUTPUT_USAGE)
+}
+
+/// Play the timeline with a fixed time step and write every
+/// frame to numbered BMP files, or to a single Y4M video if the output ends
+/// in `.y4m`. The result does not depend on how fast frames are rendered.
+pub fn export_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut common = CommonOptions::default();
+    let mut output = OutputOptions::default();
+    let mut fps: u32 = 30;
+    let mut duration = None; // The duration of the timeline unless given
+
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        if common.parse(option, &mut options)? || output.parse(option, &mut options)? {
+            continue;
+        }
+        let mut value = || options.next().ok_or_else(|| format!("Missing value for {}", option));
+        match option.as_str() {
+            "--fps" => fps = value()?.parse()?,
+            "--duration" => duration = Some(value()?.parse()?),
+            _ => return Err(format!("Unknown option '{}'", option).into()),
+        }
+    }
+    let timeline = common.timeline()?;
+    let duration: f32 = duration.unwrap_or(timeline.duration);
+    if fps == 0 || duration < 0.0 {
+        return Err("The frame rate must be positive and the duration must not be negative".into());
+    }
+    let frame_count = (duration * fps as f32).round() as usize;
+    if frame_count == 0 {
+        return Err(format!("{} seconds at {} frames per second is not a single frame", duration, fps).into());
+    }
+    let out = output.out.as_deref().ok_or("Missing --out")?;
+
+    let mut animation = Animation {
+        scene: output.scene(&common)?,
+        unlit: common.unlit,
+        timeline,
+        render_target: output.render_target(&common),
+    };
+    let mut video = if out.to_ascii_lowercase().ends_with(".y4m") {
+        Some(Y4mWriter::new(out, output.width, output.height, fps)?)
+    } else {
+        None
+    };
+
+    for frame in 0..frame_count {
+        animation.update(frame as f32 / fps as f32);
+        animation.scene.draw(&mut animation.render_target, animation.unlit);
+        match video.as_mut() {
+            Some(video) => video.write_frame(&animation.render_target.view())?,
+            None => bitmap::write_image_to_file(&animation.render_target.view(), &frame_path(out, frame, frame_count))?,
+        }
+    }
+    match video {
+        Some(video) => {
+            video.finish()?;
+            println!("{} frames written to {}", frame_count, out);
+        },
+        None => report_images(out, frame_count),
+    }
+    Ok(())
+}
+
+pub fn convert_usage() -> String {
+    "convert --model name|number --out file.obj|file.stl [--ascii]".to_string()
+}
+
+/// Write a model to a file of another format, chosen by the extension of the
+/// output. With `--parts` every part of the model becomes an object of its own
+/// in an OBJ, an STL holds all of them as a single solid.
+pub fn convert_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut common = CommonOptions::default();
+    let mut model = None;
+    let mut out = None;
+    let mut stl_format = StlFormat::Binary;
+
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        if common.parse(option, &mut options)? {
+            continue;
+        }
+        let mut value = || options.next().ok_or_else(|| format!("Missing value for {}", option));
+        match option.as_str() {
+            "--model" => model = Some(value()?.clone()),
+            "--out" => out = Some(value()?.clone()),
+            "--ascii" => stl_format = StlFormat::Ascii,
+            _ => return Err(format!("Unknown option '{}'", option).into()),
+        }
+    }
+    let model = model.ok_or("Missing --model")?;
+    let out = out.ok_or("Missing --out")?;
+    let extension = std::path::Path::new(&out).extension().unwrap_or_default().to_ascii_lowercase();
+    if extension != "obj" && extension != "stl" {
+        return Err(format!("Unknown model format of '{}', expected .obj or .stl", out).into());
+    }
+
+    let assets = AssetLoader::new(common.load_options);
+    let asset = select_asset(assets.get_assets(), &model)?;
+    let models = asset.models(common.parts);
+    if extension == "stl" {
+        let mut stl = Stl::new();
+        for model in models.iter() {
+            stl.facets.extend(Stl::from_model(model).facets);
+        }
+        stl.name = asset.name.clone();
+        stl.write_to_file(&out, stl_format)?;
+    } else {
+        Obj::from_models(&models).write_to_file(&out)?;
+    }
+    println!("Model written to {}", out);
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use crate::{float4::Float4, interpolate::Interpolate};
+
+/// A vertex in homogeneous clip space together with its per-vertex attributes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipVertex<V> {
+    pub position: Float4,
+    pub attributes: V,
+}
+
+/// Signed distances to the six frustum planes in clip space. A vertex is
+/// inside the view volume when it is non-negative for every plane, i.e.
+/// `-w <= x <= w`, `-w <= y <= w` and `0 <= z <= w`.
+const FRUSTUM_PLANES: [fn(&Float4) -> f32; 6] = [
+    |p| p.z,       // Near
+    |p| p.w - p.z, // Far
+    |p| p.w + p.x, // Left
+    |p| p.w - p.x, // Right
+    |p| p.w + p.y, // Bottom
+    |p| p.w - p.y, // Top
+];
+
+/// Clip a triangle against the view frustum.
+///
+/// The triangle is clipped to a convex polygon with the Sutherland-Hodgman
+/// algorithm, one plane at a time, and then fan triangulated again. Returns no
+/// triangles if it is entirely outside the frustum.
+pub fn clip_triangle<V: Interpolate>(triangle: [ClipVertex<V>; 3]) -> Vec<[ClipVertex<V>; 3]> {
+    let mut inside_all = true;
+    for plane in FRUSTUM_PLANES.iter() {
+        let distances = triangle.map(|v| plane(&v.position));
+        if distances.iter().all(|&d| d < 0.0) {
+            return Vec::new(); // Trivially rejected by this plane
+        }
+        inside_all &= distances.iter().all(|&d| d >= 0.0);
+    }
+
+    if inside_all {
+        return vec![triangle];
+    }
+
+    let mut polygon = triangle.to_vec();
+    for plane in FRUSTUM_PLANES.iter() {
+        polygon = clip_polygon(&polygon, plane);
+        if polygon.len() < 3 {
+            return Vec::new();
+        }
+    }
+
+    (1..polygon.len() - 1)
+        .map(|i| [polygon[0], polygon[i], polygon[i + 1]])
+        .collect()
+}
+
+fn clip_polygon<V: Interpolate>(polygon: &[ClipVertex<V>], plane: &fn(&Float4) -> f32) -> Vec<ClipVertex<V>> {
+    let mut output = Vec::with_capacity(polygon.len() + 1);
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        let current_distance = plane(&current.position);
+        let next_distance = plane(&next.position);
+
+        if current_distance >= 0.0 {
+            output.push(*current);
+        }
+
+        // Emit the intersection when the edge crosses the plane
+        if (current_distance >= 0.0) != (next_distance >= 0.0) {
+            let t = current_distance / (current_distance - next_distance);
+            output.push(ClipVertex {
+                position: Float4::lerp(current.position, next.position, t),
+                attributes: V::lerp(current.attributes, next.attributes, t),
+            });
+        }
+    }
+    output
+}
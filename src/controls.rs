@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use crate::{camera::Camera, float3::Float3, transform::Transform};
+
+/// How the mouse and keyboard move the camera of the viewer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Drag to turn around a target, scroll to zoom and middle-drag to pan.
+    Orbit,
+    /// Move with WASD, Q and E, and drag to look around.
+    Fly,
+}
+
+const TURN_SPEED: f32 = 0.01; // Radians per pixel the mouse is dragged
+const PAN_SPEED: f32 = 0.002; // Fraction of the distance to the target per pixel
+const ZOOM_STEP: f32 = 0.9; // Change of the distance per line scrolled
+const PIXELS_PER_LINE: f32 = 40.0; // Touchpads scroll in pixels, 40 of them count as one wheel line
+const FLY_SPEED: f32 = 2.0; // Units per second
+const MAX_PITCH: f32 = 1.55; // Just short of looking straight up or down
+
+pub struct CameraControls {
+    pub mode: CameraMode,
+    target: Float3, // The point the orbit camera turns around
+    distance: f32, // From the orbit camera to its target
+    position: Float3, // Of the fly camera
+    yaw: f32,
+    pitch: f32,
+    held_keys: HashSet<KeyCode>,
+    held_buttons: HashSet<MouseButton>,
+    cursor: Option<(f64, f64)>,
+}
+
+impl CameraControls {
+    /// Orbit around `target` from `distance` away, looking along +Z like
+    /// `Scene::default_camera`.
+    pub fn new(target: Float3, distance: f32) -> Self {
+        CameraControls {
+            mode: CameraMode::Orbit,
+            target,
+            distance,
+            position: target - Float3::new(0.0, 0.0, distance),
+            yaw: 0.0,
+            pitch: 0.0,
+            held_keys: HashSet::new(),
+            held_buttons: HashSet::new(),
+            cursor: None,
+        }
+    }
+
+    /// Move and turn the camera to where the controls have put it.
+    pub fn place(&self, camera: &mut Camera) {
+        let view = self.view();
+        (camera.position, camera.yaw, camera.pitch, camera.roll) = (view.position, view.yaw, view.pitch, 0.0);
+    }
+
+    // The placement of the camera in the world
+    fn view(&self) -> Transform {
+        let orientation = Transform { yaw: self.yaw, pitch: self.pitch, ..Transform::default() };
+        let position = match self.mode {
+            CameraMode::Orbit => self.target - orientation.to_world_direction(&Float3::new(0.0, 0.0, self.distance)),
+            CameraMode::Fly => self.position,
+        };
+        Transform { position, ..orientation }
+    }
+
+    // Switch modes without moving the camera
+    fn toggle_mode(&mut self) {
+        let view = self.view();
+        let forward = view.to_world_direction(&Float3::new(0.0, 0.0, 1.0));
+        self.mode = match self.mode {
+            CameraMode::Orbit => {
+                self.position = view.position;
+                CameraMode::Fly
+            },
+            CameraMode::Fly => {
+                self.target = view.position + forward * self.distance;
+                CameraMode::Orbit
+            },
+        };
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                let PhysicalKey::Code(key) = event.physical_key else {
+                    return;
+                };
+                match event.state {
+                    ElementState::Pressed if key == KeyCode::KeyC && !event.repeat => self.toggle_mode(),
+                    ElementState::Pressed => _ = self.held_keys.insert(key),
+                    ElementState::Released => _ = self.held_keys.remove(&key),
+                }
+            },
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => _ = self.held_buttons.insert(*button),
+                ElementState::Released => _ = self.held_buttons.remove(button),
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some((x, y)) = self.cursor {
+                    self.drag((position.x - x) as f32, (position.y - y) as f32);
+                }
+                self.cursor = Some((position.x, position.y));
+            },
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            WindowEvent::Focused(false) => {
+                self.held_keys.clear();
+                self.held_buttons.clear();
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                match self.mode {
+                    CameraMode::Orbit => self.distance = (self.distance * ZOOM_STEP.powf(lines)).max(0.1),
+                    CameraMode::Fly => {
+                        let forward = self.view().to_world_direction(&Float3::new(0.0, 0.0, 1.0));
+                        self.position = self.position + forward * (lines * FLY_SPEED * 0.25);
+                    },
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Rows of the window run down while y in the world runs up, so dragging
+    // down turns the camera towards +Y, which shows the model moving down
+    fn drag(&mut self, dx: f32, dy: f32) {
+        if self.held_buttons.contains(&MouseButton::Left) {
+            self.yaw -= dx * TURN_SPEED;
+            self.pitch = (self.pitch + dy * TURN_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+        } else if self.held_buttons.contains(&MouseButton::Middle) && self.mode == CameraMode::Orbit {
+            let view = self.view();
+            let right = view.to_world_direction(&Float3::new(1.0, 0.0, 0.0));
+            let up = view.to_world_direction(&Float3::new(0.0, 1.0, 0.0));
+            self.target = self.target - (right * dx + up * dy) * (self.distance * PAN_SPEED);
+        }
+    }
+
+    /// Move the fly camera for the keys held during the last `elapsed` seconds.
+    pub fn update(&mut self, elapsed: f32) {
+        if self.mode != CameraMode::Fly {
+            return;
+        }
+        let view = self.view();
+        let mut direction = Float3::zero();
+        for (key, axis) in [
+            (KeyCode::KeyW, Float3::new(0.0, 0.0, 1.0)),
+            (KeyCode::KeyS, Float3::new(0.0, 0.0, -1.0)),
+            (KeyCode::KeyD, Float3::new(1.0, 0.0, 0.0)),
+            (KeyCode::KeyA, Float3::new(-1.0, 0.0, 0.0)),
+        ] {
+            if self.held_keys.contains(&key) {
+                direction = direction + view.to_world_direction(&axis);
+            }
+        }
+        // Up and down follow the world rather than the camera
+        if self.held_keys.contains(&KeyCode::KeyE) {
+            direction = direction + Float3::new(0.0, 1.0, 0.0);
+        }
+        if self.held_keys.contains(&KeyCode::KeyQ) {
+            direction = direction - Float3::new(0.0, 1.0, 0.0);
+        }
+        if direction.length() > 0.0 {
+            self.position = self.position + direction.normalize() * (FLY_SPEED * elapsed);
+        }
+    }
+}
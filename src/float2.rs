@@ -1,7 +1,5 @@
 use std::ops::{Div, Mul, Sub, Add, AddAssign};
 
-use rand::Rng;
-
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Float2 {
     pub x: f32,
@@ -13,21 +11,8 @@ impl Float2 {
         Float2 { x, y }
     }
 
-    pub fn dot(&self, other: &Float2) -> f32 {
-        self.x * other.x + self.y * other.y
-    }
-
-    // 90 degrees clockwise rotation
-    pub fn rotate_clockwise(&self) -> Self {
-        Float2 { x: self.y, y: -self.x }
-    }
-
-    pub fn random_in_range(width: f32, height: f32) -> Self {
-        let mut rng = rand::rng();
-        Float2 {
-            x: rng.random_range(0f32..width),
-            y: rng.random_range(0f32..height),
-        }
+    pub fn length(&self) -> f32 {
+        (self.x * self.x + self.y * self.y).sqrt()
     }
 }
 
@@ -84,6 +69,17 @@ impl Mul<f32> for Float2 {
     }
 }
 
+impl Mul for Float2 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Float2 {
+            x: self.x * rhs.x,
+            y: self.y * rhs.y,
+        }
+    }
+}
+
 impl Add<f32> for Float2 {
     type Output = Self;
 
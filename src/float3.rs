@@ -1,4 +1,4 @@
-use std::ops::{Add, Mul};
+use std::ops::{Add, Mul, Neg, Sub};
 
 use rand::Rng;
 
@@ -48,6 +48,38 @@ impl Float3 {
     pub fn dot(&self, other: &Self) -> f32 {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
+
+    pub fn cross(&self, other: &Self) -> Self {
+        Float3 {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    // Scale to unit length, zero vectors are returned as is
+    pub fn normalize(&self) -> Self {
+        let length = self.length();
+        if length > 0.0 {
+            *self * (1.0 / length)
+        } else {
+            *self
+        }
+    }
+
+    // Pack a colour with components in 0..1 as opaque RGBA8
+    pub fn to_rgba8(self) -> [u8; 4] {
+        [
+            (self.r() * 255.0) as u8,
+            (self.g() * 255.0) as u8,
+            (self.b() * 255.0) as u8,
+            255,
+        ]
+    }
     
 }
 
@@ -97,4 +129,28 @@ impl Add for Float3 {
             z: self.z + other.z,
         }
     }    
+}
+
+impl Sub for Float3 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self::Output {
+        Float3 {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z,
+        }
+    }
+}
+
+impl Neg for Float3 {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Float3 {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
 }
\ No newline at end of file
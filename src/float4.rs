@@ -0,0 +1,42 @@
+use std::ops::{Add, Mul};
+
+/// A homogeneous coordinate, used for vertices in clip space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Float4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Float4 {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Float4 { x, y, z, w }
+    }
+}
+
+impl Mul<f32> for Float4 {
+    type Output = Self;
+
+    fn mul(self, scalar: f32) -> Self::Output {
+        Float4 {
+            x: self.x * scalar,
+            y: self.y * scalar,
+            z: self.z * scalar,
+            w: self.w * scalar,
+        }
+    }
+}
+
+impl Add for Float4 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self::Output {
+        Float4 {
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+            w: self.w + other.w,
+        }
+    }
+}
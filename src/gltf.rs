@@ -0,0 +1,639 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    float2::Float2,
+    float3::Float3,
+    json::Json,
+    material::{Material, TextureMap, TextureOptions},
+    texture::{AddressMode, Texture},
+    transform::Matrix4,
+};
+
+/// Part of a mesh drawn with a single material, already split into triangles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfPrimitive {
+    pub positions: Vec<Float3>,
+    pub normals: Option<Vec<Float3>>,
+    pub uvs: Option<Vec<Float2>>, // With (0, 0) at the bottom-left of the texture, unlike glTF
+    pub colors: Option<Vec<Float3>>,
+    pub triangles: Vec<[usize; 3]>, // Counterclockwise indices into the vertex attributes
+    pub material: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfMesh {
+    pub name: String,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+/// A node of the scene hierarchy, placed relative to its parent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfNode {
+    pub name: String,
+    pub mesh: Option<usize>,
+    pub children: Vec<usize>,
+    pub transform: Matrix4,
+}
+
+/// A mesh placed in the scene by a node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshInstance {
+    pub node: usize,
+    pub mesh: usize,
+    pub world: Matrix4, // The transforms of the node and all of its ancestors combined
+}
+
+/// The static meshes of a glTF 2.0 file. Animations, skins, morph targets and
+/// cameras are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gltf {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<Material>,
+    pub nodes: Vec<GltfNode>,
+    pub scene: Vec<usize>, // The root nodes of the scene that is shown
+}
+
+// Most elements read from an accessor without a buffer view, which takes no
+// space in the file however many elements it claims
+const MAX_ZERO_ELEMENTS: usize = 1 << 24;
+
+// The chunk types of a binary glTF file
+const GLB_JSON_CHUNK: u32 = 0x4e4f534a;
+const GLB_BIN_CHUNK: u32 = 0x004e4942;
+
+impl Gltf {
+    /// Read a `.gltf` file with embedded or external buffers, or a binary `.glb` file.
+    pub fn read_from_file(file_path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(file_path).map_err(|e| format!("{}: {}", file_path, e))?;
+        Self::parse(&bytes, file_path)
+    }
+
+    // JSON errors carry their line and column, the others only the path
+    fn parse(bytes: &[u8], file_path: &str) -> Result<Self, String> {
+        let in_file = |e: String| format!("{}: {}", file_path, e);
+        let (json, binary) = if bytes.starts_with(b"glTF") {
+            let (json, binary) = Self::read_glb(bytes).map_err(in_file)?;
+            (json, Some(binary))
+        } else {
+            (std::str::from_utf8(bytes).map_err(|_| in_file("The file is not valid text".to_string()))?, None)
+        };
+        let json = Json::parse(json, file_path)?;
+        let document = Document::load(&json, binary, file_path).map_err(in_file)?;
+        document.convert().map_err(in_file)
+    }
+
+    // Split a binary glTF file into its JSON and binary chunks
+    fn read_glb(bytes: &[u8]) -> Result<(&str, &[u8]), String> {
+        let u32_at = |offset: usize| bytes.get(offset..offset + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+        let version = u32_at(4).ok_or("Truncated header")?;
+        if version != 2 {
+            return Err(format!("Unsupported glTF version {}", version));
+        }
+        let length = (u32_at(8).ok_or("Truncated header")? as usize).min(bytes.len());
+
+        let (mut json, mut binary) = (None, None);
+        let mut position = 12;
+        while position + 8 <= length {
+            let chunk_length = u32_at(position).unwrap() as usize;
+            let chunk_type = u32_at(position + 4).unwrap();
+            let data = bytes.get(position + 8..position + 8 + chunk_length).ok_or("Truncated chunk")?;
+            match chunk_type {
+                GLB_JSON_CHUNK if json.is_none() => json = Some(data),
+                GLB_BIN_CHUNK if binary.is_none() => binary = Some(data),
+                _ => {}, // Unknown chunks must be skipped
+            }
+            position += 8 + chunk_length.div_ceil(4) * 4;
+        }
+        let json = std::str::from_utf8(json.ok_or("Missing JSON chunk")?).map_err(|_| "The JSON chunk is not valid text")?;
+        Ok((json, binary.unwrap_or(&[])))
+    }
+
+    /// Every mesh in the scene along with where it is placed, in hierarchy order.
+    pub fn mesh_instances(&self) -> Vec<MeshInstance> {
+        let mut instances = Vec::new();
+        let mut stack: Vec<(usize, Matrix4)> = self.scene.iter().rev().map(|&node| (node, Matrix4::identity())).collect();
+        while let Some((node, parent)) = stack.pop() {
+            let world = parent.multiply(&self.nodes[node].transform);
+            if let Some(mesh) = self.nodes[node].mesh {
+                instances.push(MeshInstance { node, mesh, world });
+            }
+            stack.extend(self.nodes[node].children.iter().rev().map(|&child| (child, world)));
+        }
+        instances
+    }
+
+    /// The name of a mesh instance, that of its node or else that of its mesh.
+    pub fn instance_name(&self, instance: &MeshInstance) -> String {
+        let node = &self.nodes[instance.node].name;
+        if node.is_empty() { self.meshes[instance.mesh].name.clone() } else { node.clone() }
+    }
+}
+
+/// The JSON of a glTF file with its buffers loaded.
+struct Document<'a> {
+    json: &'a Json,
+    buffers: Vec<Vec<u8>>,
+    directory: &'a Path, // External files are relative to the glTF file
+}
+
+/// An accessor read into floating point values, `components` per element.
+struct AccessorData {
+    values: Vec<f64>,
+    components: usize,
+}
+
+impl AccessorData {
+    fn element(&self, index: usize) -> &[f64] {
+        &self.values[index * self.components..(index + 1) * self.components]
+    }
+
+    fn count(&self) -> usize {
+        self.values.len() / self.components
+    }
+
+    fn float3(&self) -> Result<Vec<Float3>, String> {
+        if self.components < 3 {
+            return Err("Expected an accessor with at least 3 components".to_string());
+        }
+        Ok((0..self.count()).map(|i| {
+            let v = self.element(i);
+            Float3::new(v[0] as f32, v[1] as f32, v[2] as f32)
+        }).collect())
+    }
+}
+
+impl<'a> Document<'a> {
+    fn load(json: &'a Json, binary: Option<&[u8]>, file_path: &'a str) -> Result<Self, String> {
+        let version = json.get("asset").and_then(|asset| asset.get("version")).and_then(Json::as_str).unwrap_or("");
+        if !version.starts_with("2.") {
+            return Err(format!("Unsupported glTF version '{}'", version));
+        }
+        let directory = Path::new(file_path).parent().unwrap_or(Path::new(""));
+        let mut document = Document { json, buffers: Vec::new(), directory };
+
+        for (index, buffer) in json.get("buffers").map(Json::items).unwrap_or_default().iter().enumerate() {
+            let data = match buffer.get("uri").and_then(Json::as_str) {
+                Some(uri) => document.read_uri(uri).map_err(|e| format!("Buffer {}: {}", index, e))?,
+                // Only the first buffer of a binary file may refer to its binary chunk
+                None => match binary {
+                    Some(binary) if index == 0 => binary.to_vec(),
+                    _ => return Err(format!("Buffer {} has no data", index)),
+                },
+            };
+            let length = buffer.get("byteLength").and_then(Json::as_usize).unwrap_or(data.len());
+            if data.len() < length {
+                return Err(format!("Buffer {} is shorter than its byteLength", index));
+            }
+            document.buffers.push(data);
+        }
+        Ok(document)
+    }
+
+    // Read the data of a data URI, or of a file relative to the glTF file
+    fn read_uri(&self, uri: &str) -> Result<Vec<u8>, String> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let (header, data) = data.split_once(',').ok_or("Invalid data URI")?;
+            if !header.ends_with(";base64") {
+                return Err("Only base64 data URIs are supported".to_string());
+            }
+            return decode_base64(data);
+        }
+        let path = self.directory.join(decode_percent(uri));
+        std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn array(&self, name: &str) -> &'a [Json] {
+        self.json.get(name).map(Json::items).unwrap_or_default()
+    }
+
+    // The element of a top-level array, e.g. `accessors`, with a readable error if it is missing
+    fn item(&self, array: &str, index: usize) -> Result<&'a Json, String> {
+        self.array(array).get(index).ok_or_else(|| format!("Index {} into {} is out of bounds", index, array))
+    }
+
+    // The bytes of a buffer view, and its stride if it has one
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], Option<usize>), String> {
+        let view = self.item("bufferViews", index)?;
+        let buffer = view.get("buffer").and_then(Json::as_usize).ok_or("Buffer view without a buffer")?;
+        let buffer = self.buffers.get(buffer).ok_or_else(|| format!("Buffer {} is out of bounds", buffer))?;
+        let offset = view.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let length = view.get("byteLength").and_then(Json::as_usize).ok_or("Buffer view without a byteLength")?;
+        let data = offset.checked_add(length).and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| format!("Buffer view {} exceeds its buffer", index))?;
+        Ok((data, view.get("byteStride").and_then(Json::as_usize)))
+    }
+
+    fn accessor(&self, index: usize) -> Result<AccessorData, String> {
+        let context = |e: String| format!("Accessor {}: {}", index, e);
+        let accessor = self.item("accessors", index)?;
+        let count = accessor.get("count").and_then(Json::as_usize).ok_or_else(|| context("Missing count".to_string()))?;
+        let components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            other => return Err(context(format!("Unsupported type {:?}", other.unwrap_or("")))),
+        };
+        let component_type = accessor.get("componentType").and_then(Json::as_usize).unwrap_or(0);
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(context(format!("Unsupported component type {}", component_type))),
+        };
+        let normalized = accessor.get("normalized").and_then(Json::as_bool).unwrap_or(false);
+        if accessor.get("sparse").is_some() {
+            return Err(context("Sparse accessors are not supported".to_string()));
+        }
+
+        // Accessors without a buffer view are all zeros
+        let Some(view) = accessor.get("bufferView").and_then(Json::as_usize) else {
+            if count > MAX_ZERO_ELEMENTS {
+                return Err(context(format!("{} elements without a buffer view is too many", count)));
+            }
+            return Ok(AccessorData { values: vec![0.0; count * components], components });
+        };
+        let (data, stride) = self.buffer_view(view).map_err(context)?;
+        let offset = accessor.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let element_size = size * components;
+        let stride = stride.unwrap_or(element_size);
+        if stride < element_size {
+            return Err(context(format!("The byte stride {} is smaller than an element", stride)));
+        }
+
+        // Check the last element fits before allocating, so the count from the file cannot be too large
+        if count > 0 {
+            let end = (count - 1).checked_mul(stride)
+                .and_then(|last| last.checked_add(offset))
+                .and_then(|last| last.checked_add(element_size));
+            if end.is_none_or(|end| end > data.len()) {
+                return Err(context("Exceeds its buffer view".to_string()));
+            }
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let start = offset + element * stride + component * size;
+                let bytes = &data[start..start + size];
+                // Normalized integers map onto 0..1, or -1..1 if they are signed
+                let value = match component_type {
+                    5120 => { let v = bytes[0] as i8 as f64; if normalized { (v / 127.0).max(-1.0) } else { v } },
+                    5121 => { let v = bytes[0] as f64; if normalized { v / 255.0 } else { v } },
+                    5122 => { let v = i16::from_le_bytes([bytes[0], bytes[1]]) as f64; if normalized { (v / 32767.0).max(-1.0) } else { v } },
+                    5123 => { let v = u16::from_le_bytes([bytes[0], bytes[1]]) as f64; if normalized { v / 65535.0 } else { v } },
+                    5125 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    _ => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                };
+                values.push(value);
+            }
+        }
+        Ok(AccessorData { values, components })
+    }
+
+    fn convert(&self) -> Result<Gltf, String> {
+        let textures = self.load_textures();
+        let materials = self.array("materials").iter().enumerate()
+            .map(|(index, material)| self.material(index, material, &textures))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut meshes = Vec::new();
+        for (index, mesh) in self.array("meshes").iter().enumerate() {
+            let mut primitives = Vec::new();
+            for primitive in mesh.get("primitives").map(Json::items).unwrap_or_default() {
+                if let Some(primitive) = self.primitive(primitive).map_err(|e| format!("Mesh {}: {}", index, e))? {
+                    primitives.push(primitive);
+                }
+            }
+            let name = mesh.get("name").and_then(Json::as_str).map_or_else(|| format!("mesh{}", index), str::to_string);
+            meshes.push(GltfMesh { name, primitives });
+        }
+
+        let nodes = self.array("nodes").iter().enumerate()
+            .map(|(index, node)| self.node(node, meshes.len()).map_err(|e| format!("Node {}: {}", index, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parents = node_parents(&nodes)?;
+
+        // Without scenes every node that is not the child of another is a root
+        let scene = match self.json.get("scene").and_then(Json::as_usize).or(if self.array("scenes").is_empty() { None } else { Some(0) }) {
+            Some(scene) => self.item("scenes", scene)?.get("nodes").map(Json::items).unwrap_or_default().iter()
+                .map(|node| node.as_usize().filter(|&node| node < nodes.len() && parents[node].is_none())
+                    .ok_or_else(|| format!("Scene {} has an invalid node or one that is not a root", scene)))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => (0..nodes.len()).filter(|&node| parents[node].is_none()).collect(),
+        };
+
+        Ok(Gltf { meshes, materials, nodes, scene })
+    }
+
+    // Images that fail to load are reported and left out
+    fn load_textures(&self) -> Vec<Option<(Arc<Texture>, Option<String>)>> {
+        self.array("images").iter().enumerate().map(|(index, image)| {
+            let (data, path) = match (image.get("uri").and_then(Json::as_str), image.get("bufferView").and_then(Json::as_usize)) {
+                (Some(uri), _) => {
+                    let path = (!uri.starts_with("data:"))
+                        .then(|| self.directory.join(decode_percent(uri)).to_string_lossy().into_owned());
+                    (self.read_uri(uri), path)
+                },
+                (None, Some(view)) => (self.buffer_view(view).map(|(data, _)| data.to_vec()), None),
+                (None, None) => (Err("No image data".to_string()), None),
+            };
+            match data.and_then(|data| Texture::decode(&data)) {
+                Ok(texture) => Some((Arc::new(texture), path)),
+                Err(e) => {
+                    eprintln!("Failed to load image {}: {}", index, e);
+                    None
+                },
+            }
+        }).collect()
+    }
+
+    fn material(&self, index: usize, material: &Json, textures: &[Option<(Arc<Texture>, Option<String>)>]) -> Result<Material, String> {
+        let name = material.get("name").and_then(Json::as_str).map_or_else(|| format!("material{}", index), str::to_string);
+        let mut result = Material::new(&name);
+        result.diffuse = Float3::new(1.0, 1.0, 1.0);
+
+        let pbr = material.get("pbrMetallicRoughness");
+        if let Some([r, g, b, a]) = pbr.and_then(|pbr| pbr.get("baseColorFactor")).and_then(Json::as_numbers).as_deref() {
+            result.diffuse = Float3::new(*r, *g, *b);
+            result.dissolve = *a;
+        }
+        if let Some([r, g, b]) = material.get("emissiveFactor").and_then(Json::as_numbers).as_deref() {
+            result.emissive = Float3::new(*r, *g, *b);
+        }
+
+        if let Some(info) = pbr.and_then(|pbr| pbr.get("baseColorTexture")) {
+            if info.get("texCoord").and_then(Json::as_usize).unwrap_or(0) != 0 {
+                eprintln!("Material {}: Only TEXCOORD_0 is supported, the base colour texture may look wrong", name);
+            }
+            let texture = info.get("index").and_then(Json::as_usize).ok_or_else(|| format!("Material {}: Texture without an index", name))?;
+            let texture = self.item("textures", texture)?;
+            let source = texture.get("source").and_then(Json::as_usize);
+            if let Some((texture_data, path)) = source.and_then(|source| textures.get(source).cloned().flatten()) {
+                // Only the horizontal wrap mode of the sampler is used, like -clamp in MTL files
+                let wrap = match texture.get("sampler").and_then(Json::as_usize) {
+                    Some(sampler) => self.item("samplers", sampler)?.get("wrapS").and_then(Json::as_usize).unwrap_or(10497),
+                    None => 10497,
+                };
+                let options = TextureOptions {
+                    address_mode: match wrap {
+                        33071 => AddressMode::Clamp,
+                        33648 => AddressMode::Mirror,
+                        _ => AddressMode::Wrap,
+                    },
+                    ..TextureOptions::default()
+                };
+                result.diffuse_map = Some(TextureMap { texture: texture_data, path, options });
+            }
+        }
+        Ok(result)
+    }
+
+    // Returns `None` for primitives that are not made of triangles
+    fn primitive(&self, primitive: &Json) -> Result<Option<GltfPrimitive>, String> {
+        let mode = primitive.get("mode").and_then(Json::as_usize).unwrap_or(4);
+        if !(4..=6).contains(&mode) {
+            eprintln!("Skipping a primitive of points or lines (mode {})", mode);
+            return Ok(None);
+        }
+
+        let attributes = primitive.get("attributes").ok_or("Primitive without attributes")?;
+        let attribute = |name: &str| attributes.get(name).and_then(Json::as_usize).map(|index| self.accessor(index)).transpose();
+        let positions = attribute("POSITION")?.ok_or("Primitive without positions")?.float3()?;
+        let count = positions.len();
+        let check = |name: &str, length: usize| {
+            if length == count { Ok(()) } else { Err(format!("{} has {} elements but there are {} positions", name, length, count)) }
+        };
+
+        let normals = attribute("NORMAL")?.map(|normals| normals.float3()).transpose()?;
+        let uvs = attribute("TEXCOORD_0")?.map(|uvs| {
+            (0..uvs.count()).map(|i| {
+                let uv = uvs.element(i);
+                Float2::new(uv[0] as f32, 1.0 - uv.get(1).copied().unwrap_or(0.0) as f32)
+            }).collect::<Vec<_>>()
+        });
+        let colors = attribute("COLOR_0")?.map(|colors| colors.float3()).transpose()?;
+        check("NORMAL", normals.as_ref().map_or(count, Vec::len))?;
+        check("TEXCOORD_0", uvs.as_ref().map_or(count, Vec::len))?;
+        check("COLOR_0", colors.as_ref().map_or(count, Vec::len))?;
+
+        let indices: Vec<usize> = match primitive.get("indices").and_then(Json::as_usize) {
+            Some(accessor) => self.accessor(accessor)?.values.into_iter().map(|index| index as usize).collect(),
+            None => (0..count).collect(),
+        };
+        if let Some(index) = indices.iter().find(|&&index| index >= count) {
+            return Err(format!("Index {} is out of bounds for {} vertices", index, count));
+        }
+
+        let triangles = match mode {
+            4 => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+            // Every other triangle of a strip is reversed to keep the winding
+            5 => (2..indices.len()).map(|i| {
+                if i.is_multiple_of(2) { [indices[i - 2], indices[i - 1], indices[i]] } else { [indices[i - 1], indices[i - 2], indices[i]] }
+            }).collect(),
+            _ => (2..indices.len()).map(|i| [indices[0], indices[i - 1], indices[i]]).collect(),
+        };
+
+        Ok(Some(GltfPrimitive {
+            positions,
+            normals,
+            uvs,
+            colors,
+            triangles,
+            material: primitive.get("material").and_then(Json::as_usize),
+        }))
+    }
+
+    fn node(&self, node: &Json, mesh_count: usize) -> Result<GltfNode, String> {
+        let transform = match node.get("matrix").and_then(Json::as_numbers) {
+            Some(matrix) => {
+                let matrix: [f32; 16] = matrix.try_into().map_err(|_| "The matrix does not have 16 elements")?;
+                Matrix4 { columns: std::array::from_fn(|column| std::array::from_fn(|row| matrix[column * 4 + row])) }
+            },
+            None => {
+                let vector = |name: &str, default: Float3| match node.get(name).and_then(Json::as_numbers).as_deref() {
+                    Some(&[x, y, z]) => Float3::new(x, y, z),
+                    _ => default,
+                };
+                let rotation = match node.get("rotation").and_then(Json::as_numbers).as_deref() {
+                    Some(&[x, y, z, w]) => [x, y, z, w],
+                    _ => [0.0, 0.0, 0.0, 1.0],
+                };
+                Matrix4::from_translation_rotation_scale(vector("translation", Float3::zero()), rotation, vector("scale", Float3::new(1.0, 1.0, 1.0)))
+            },
+        };
+
+        let mesh = node.get("mesh").and_then(Json::as_usize);
+        if mesh.is_some_and(|mesh| mesh >= mesh_count) {
+            return Err("Mesh index out of bounds".to_string());
+        }
+        let children = node.get("children").map(Json::items).unwrap_or_default().iter()
+            .map(|child| child.as_usize().filter(|&child| child < self.array("nodes").len()).ok_or("Invalid child node"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let name = node.get("name").and_then(Json::as_str).unwrap_or("").to_string();
+        Ok(GltfNode { name, mesh, children, transform })
+    }
+}
+
+// The parent of every node. The nodes must form a forest, where no node has
+// more than one parent or is its own ancestor.
+fn node_parents(nodes: &[GltfNode]) -> Result<Vec<Option<usize>>, String> {
+    let mut parents = vec![None; nodes.len()];
+    for (index, node) in nodes.iter().enumerate() {
+        for &child in node.children.iter() {
+            if parents[child].replace(index).is_some() {
+                return Err(format!("Node {} has more than one parent", child));
+            }
+        }
+    }
+
+    // With single parents, nodes that cannot be reached from a root are part of a cycle
+    let mut stack: Vec<usize> = (0..nodes.len()).filter(|&node| parents[node].is_none()).collect();
+    let mut reached = 0;
+    while let Some(node) = stack.pop() {
+        reached += 1;
+        stack.extend_from_slice(&nodes[node].children);
+    }
+    if reached < nodes.len() {
+        return Err("The node hierarchy contains a cycle".to_string());
+    }
+    Ok(parents)
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut result = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut bit_count) = (0u32, 0);
+    for byte in text.bytes().take_while(|&byte| byte != b'=') {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(format!("Invalid base64 character '{}'", byte as char)),
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            result.push((bits >> bit_count) as u8);
+        }
+    }
+    Ok(result)
+}
+
+// Undo the percent encoding of a URI, e.g. "%20" for spaces
+fn decode_percent(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = uri.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(value)) => {
+                result.push(value);
+                i += 3;
+            },
+            (byte, _) => {
+                result.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Model;
+
+    // A glTF file with one triangle, whose position accessor and nodes are given
+    fn document(accessor: &str, nodes: &str) -> String {
+        format!(r#"{{
+  "asset": {{ "version": "2.0" }},
+  "buffers": [{{ "uri": "data:application/octet-stream;base64,{}", "byteLength": 36 }}],
+  "bufferViews": [{{ "buffer": 0, "byteLength": 36 }}],
+  "accessors": [{}],
+  "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }} }}] }}],
+  "nodes": {}
+}}"#, "A".repeat(48), accessor, nodes)
+    }
+
+    const POSITIONS: &str = r#"{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }"#;
+
+    fn parse(text: &str) -> Result<Gltf, String> {
+        Gltf::parse(text.as_bytes(), "scene.gltf")
+    }
+
+    #[test]
+    fn reads_a_triangle() {
+        let gltf = parse(&document(POSITIONS, r#"[{ "children": [1] }, { "mesh": 0 }]"#)).unwrap();
+        assert_eq!(gltf.meshes[0].primitives[0].triangles, [[0, 1, 2]]);
+        assert_eq!(gltf.scene, [0]);
+        assert_eq!(gltf.mesh_instances().len(), 1);
+    }
+
+    #[test]
+    fn node_transforms_become_the_model_transform() {
+        let nodes = r#"[{ "children": [1], "translation": [1, 0, 0] }, { "mesh": 0, "translation": [0, 2, 0] }]"#;
+        let gltf = parse(&document(POSITIONS, nodes)).unwrap();
+        let model = Model::from_gltf(&gltf, &gltf.mesh_instances()[0]);
+        // The vertices stay where the mesh has them, only the transform moves them
+        let triangle = &model.triangles[0];
+        assert_eq!([triangle.a, triangle.b, triangle.c], [Float3::zero(); 3]);
+        assert_eq!(model.transform.to_world_point(&triangle.a), Float3::new(1.0, 2.0, 0.0));
+        assert_eq!(model.world_triangles().next().unwrap().a, Float3::new(1.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn json_errors_carry_the_line_and_column() {
+        assert_eq!(parse("{\n  \"asset\": }").unwrap_err(), "scene.gltf:2:12: Expected a value");
+        assert_eq!(parse(&document(POSITIONS, "[")).unwrap_err(), "scene.gltf:8:1: Expected a value");
+    }
+
+    #[test]
+    fn huge_accessor_counts_fail_without_allocating() {
+        let accessor = r#"{ "bufferView": 0, "componentType": 5126, "count": 1e15, "type": "VEC3" }"#;
+        assert_eq!(parse(&document(accessor, "[]")).unwrap_err(), "scene.gltf: Mesh 0: Accessor 0: Exceeds its buffer view");
+        let accessor = r#"{ "componentType": 5126, "count": 1e15, "type": "VEC3" }"#;
+        assert_eq!(parse(&document(accessor, "[]")).unwrap_err(),
+            "scene.gltf: Mesh 0: Accessor 0: 1000000000000000 elements without a buffer view is too many");
+        let accessor = r#"{ "bufferView": 0, "byteOffset": 1, "componentType": 5126, "count": 3, "type": "VEC3" }"#;
+        assert_eq!(parse(&document(accessor, "[]")).unwrap_err(), "scene.gltf: Mesh 0: Accessor 0: Exceeds its buffer view");
+    }
+
+    #[test]
+    fn stride_smaller_than_an_element() {
+        let text = document(POSITIONS, "[]").replace(r#""byteLength": 36 }]"#, r#""byteLength": 36, "byteStride": 4 }]"#);
+        assert_eq!(parse(&text).unwrap_err(), "scene.gltf: Mesh 0: Accessor 0: The byte stride 4 is smaller than an element");
+    }
+
+    #[test]
+    fn nodes_must_form_a_forest() {
+        assert_eq!(parse(&document(POSITIONS, r#"[{ "children": [1, 1] }, {}]"#)).unwrap_err(), "scene.gltf: Node 1 has more than one parent");
+        assert_eq!(parse(&document(POSITIONS, r#"[{ "children": [1] }, { "children": [0] }]"#)).unwrap_err(),
+            "scene.gltf: The node hierarchy contains a cycle");
+        assert_eq!(parse(&document(POSITIONS, r#"[{ "children": [0] }]"#)).unwrap_err(), "scene.gltf: The node hierarchy contains a cycle");
+        assert_eq!(parse(&document(POSITIONS, r#"[{ "children": [2] }]"#)).unwrap_err(), "scene.gltf: Node 0: Invalid child node");
+
+        let text = document(POSITIONS, r#"[{ "children": [1] }, {}], "scenes": [{ "nodes": [1] }]"#);
+        assert_eq!(parse(&text).unwrap_err(), "scene.gltf: Scene 0 has an invalid node or one that is not a root");
+    }
+
+    #[test]
+    fn invalid_glb_containers_are_errors() {
+        assert_eq!(Gltf::parse(b"glTF\x02\0\0", "scene.glb").unwrap_err(), "scene.glb: Truncated header");
+        assert_eq!(Gltf::parse(b"glTF\x01\0\0\0\x0c\0\0\0", "scene.glb").unwrap_err(), "scene.glb: Unsupported glTF version 1");
+        assert_eq!(Gltf::parse(b"glTF\x02\0\0\0\x20\0\0\0\x40\0\0\0JSON{}", "scene.glb").unwrap_err(), "scene.glb: Truncated chunk");
+    }
+
+    #[test]
+    fn invalid_documents_are_errors() {
+        assert_eq!(parse("").unwrap_err(), "scene.gltf:1:1: Unexpected end of the document");
+        assert_eq!(Gltf::parse(b"\xff\xfe", "scene.gltf").unwrap_err(), "scene.gltf: The file is not valid text");
+        assert_eq!(parse(r#"{ "asset": { "version": "1.0" } }"#).unwrap_err(), "scene.gltf: Unsupported glTF version '1.0'");
+        let text = document(POSITIONS, "[]").replace("AAAA", "A!AA");
+        assert_eq!(parse(&text).unwrap_err(), "scene.gltf: Buffer 0: Invalid base64 character '!'");
+    }
+}
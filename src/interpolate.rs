@@ -0,0 +1,83 @@
+use crate::{float2::Float2, float3::Float3, float4::Float4};
+
+/// Per-vertex data that can be blended across the surface of a triangle,
+/// e.g. colours, texture coordinates and normals.
+pub trait Interpolate: Copy {
+    /// Blend three values with the given barycentric weights.
+    fn weighted_sum(a: Self, b: Self, c: Self, weights: Float3) -> Self;
+
+    /// Blend between two values, `t = 0` gives `a` and `t = 1` gives `b`.
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        Self::weighted_sum(a, b, a, Float3::new(1.0 - t, t, 0.0))
+    }
+}
+
+impl Interpolate for () {
+    fn weighted_sum(_: Self, _: Self, _: Self, _: Float3) -> Self {}
+}
+
+impl Interpolate for f32 {
+    fn weighted_sum(a: Self, b: Self, c: Self, weights: Float3) -> Self {
+        a * weights.x + b * weights.y + c * weights.z
+    }
+}
+
+impl Interpolate for Float2 {
+    fn weighted_sum(a: Self, b: Self, c: Self, weights: Float3) -> Self {
+        a * weights.x + b * weights.y + c * weights.z
+    }
+}
+
+impl Interpolate for Float3 {
+    fn weighted_sum(a: Self, b: Self, c: Self, weights: Float3) -> Self {
+        a * weights.x + b * weights.y + c * weights.z
+    }
+}
+
+impl Interpolate for Float4 {
+    fn weighted_sum(a: Self, b: Self, c: Self, weights: Float3) -> Self {
+        a * weights.x + b * weights.y + c * weights.z
+    }
+}
+
+impl<A: Interpolate, B: Interpolate> Interpolate for (A, B) {
+    fn weighted_sum(a: Self, b: Self, c: Self, weights: Float3) -> Self {
+        (
+            A::weighted_sum(a.0, b.0, c.0, weights),
+            B::weighted_sum(a.1, b.1, c.1, weights),
+        )
+    }
+}
+
+impl<A: Interpolate, B: Interpolate, C: Interpolate> Interpolate for (A, B, C) {
+    fn weighted_sum(a: Self, b: Self, c: Self, weights: Float3) -> Self {
+        (
+            A::weighted_sum(a.0, b.0, c.0, weights),
+            B::weighted_sum(a.1, b.1, c.1, weights),
+            C::weighted_sum(a.2, b.2, c.2, weights),
+        )
+    }
+}
+
+impl<A: Interpolate, B: Interpolate, C: Interpolate, D: Interpolate> Interpolate for (A, B, C, D) {
+    fn weighted_sum(a: Self, b: Self, c: Self, weights: Float3) -> Self {
+        (
+            A::weighted_sum(a.0, b.0, c.0, weights),
+            B::weighted_sum(a.1, b.1, c.1, weights),
+            C::weighted_sum(a.2, b.2, c.2, weights),
+            D::weighted_sum(a.3, b.3, c.3, weights),
+        )
+    }
+}
+
+/// Convert screen-space barycentric weights into perspective-correct ones.
+///
+/// Attributes vary linearly in view space, not in screen space, so the screen
+/// weights are scaled by `1 / depth` of each vertex and renormalised. Returns
+/// the corrected weights together with the interpolated view-space depth.
+pub fn perspective_correct(weights: Float3, inverse_depths: Float3) -> (Float3, f32) {
+    let scaled = weights * inverse_depths;
+    let inverse_depth = scaled.x + scaled.y + scaled.z;
+    let depth = 1.0 / inverse_depth;
+    (scaled * depth, depth)
+}
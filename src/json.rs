@@ -0,0 +1,292 @@
+/// A JSON value. Object members keep the order they were written in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parse a JSON document. Errors contain the line and column they occurred
+    /// at, after the path of the file the text was read from.
+    pub fn parse(text: &str, file_path: &str) -> Result<Self, String> {
+        let mut parser = Parser { text, file_path, position: 0, depth: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position < text.len() {
+            return Err(parser.error("Unexpected data after the end of the document"));
+        }
+        Ok(value)
+    }
+
+    /// The member of an object with the given name, `None` for other values.
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(key, _)| key == name).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// The value as an index or count, `None` if it is not a non-negative integer.
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64().filter(|number| *number >= 0.0 && number.fract() == 0.0).map(|number| number as usize)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The items of an array. Any other value has no items.
+    pub fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    /// The items of an array of numbers, `None` if any item is not a number.
+    pub fn as_numbers(&self) -> Option<Vec<f32>> {
+        match self {
+            Json::Array(items) => items.iter().map(|item| item.as_f64().map(|number| number as f32)).collect(),
+            _ => None,
+        }
+    }
+}
+
+// Deepest nesting of arrays and objects, which are parsed recursively
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    text: &'a str,
+    file_path: &'a str,
+    position: usize, // In bytes
+    depth: usize, // Number of arrays and objects the parser is inside of
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        let before = &self.text[..self.position];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        format!("{}:{}:{}: {}", self.file_path, line, column, message)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if !self.text[self.position..].starts_with(literal) {
+            return Err(self.error(&format!("Expected '{}'", literal)));
+        }
+        self.position += literal.len();
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{' | b'[') => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error("Too deeply nested"));
+                }
+                self.depth += 1;
+                let value = if self.peek() == Some(b'{') { self.object() } else { self.array() };
+                self.depth -= 1;
+                value
+            },
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("Expected a value")),
+            None => Err(self.error("Unexpected end of the document")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect("{")?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                },
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect("[")?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                },
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        while matches!(self.peek(), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.position += 1;
+        }
+        let token = &self.text[start..self.position];
+        token.parse().map(Json::Number).map_err(|_| {
+            self.position = start;
+            self.error(&format!("Invalid number '{}'", token))
+        })
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut result = String::new();
+        loop {
+            let Some(c) = self.text[self.position..].chars().next() else {
+                return Err(self.error("Unterminated string"));
+            };
+            self.position += c.len_utf8();
+            match c {
+                '"' => return Ok(result),
+                '\\' => {
+                    let escape = self.peek().ok_or_else(|| self.error("Unterminated string"))?;
+                    self.position += 1;
+                    result.push(match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape sequence")),
+                    });
+                },
+                _ => result.push(c),
+            }
+        }
+    }
+
+    // The code point of a \u escape, which may be a surrogate pair
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let hex = |parser: &mut Self| -> Result<u32, String> {
+            let digits = parser.text.get(parser.position..parser.position + 4).ok_or_else(|| parser.error("Invalid unicode escape"))?;
+            let value = u32::from_str_radix(digits, 16).map_err(|_| parser.error("Invalid unicode escape"))?;
+            parser.position += 4;
+            Ok(value)
+        };
+        let high = hex(self)?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            self.expect("\\u")?;
+            let low = hex(self)?;
+            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Json, String> {
+        Json::parse(text, "data.json")
+    }
+
+    #[test]
+    fn parses_values() {
+        let json = parse("{\"a\": [1, -2.5e1, true, null], \"b\": \"x\\u00e9\\ud83d\\ude00\"}").unwrap();
+        assert_eq!(json.get("a").unwrap().items(), [Json::Number(1.0), Json::Number(-25.0), Json::Bool(true), Json::Null]);
+        assert_eq!(json.get("b").unwrap().as_str(), Some("xé😀"));
+    }
+
+    #[test]
+    fn errors_carry_the_line_and_column() {
+        assert_eq!(parse("{\n  \"a\": 1,\n  \"b\" 2\n}").unwrap_err(), "data.json:3:7: Expected ':'");
+        assert_eq!(parse("[1,\n 2 3]").unwrap_err(), "data.json:2:4: Expected ',' or ']'");
+        assert_eq!(parse("{\"é\": 1.2.3}").unwrap_err(), "data.json:1:7: Invalid number '1.2.3'");
+        assert_eq!(parse("[tru]").unwrap_err(), "data.json:1:2: Expected 'true'");
+        assert_eq!(parse("\"\\q\"").unwrap_err(), "data.json:1:4: Invalid escape sequence");
+        assert_eq!(parse("{} {}").unwrap_err(), "data.json:1:4: Unexpected data after the end of the document");
+    }
+
+    #[test]
+    fn unexpected_end_reports_its_position() {
+        assert_eq!(parse("").unwrap_err(), "data.json:1:1: Unexpected end of the document");
+        assert_eq!(parse("{\"a\": [1,\n").unwrap_err(), "data.json:2:1: Unexpected end of the document");
+        assert_eq!(parse("{\"a").unwrap_err(), "data.json:1:4: Unterminated string");
+        assert_eq!(parse("\"\\u12").unwrap_err(), "data.json:1:4: Invalid unicode escape");
+    }
+
+    #[test]
+    fn invalid_values_report_their_position() {
+        assert_eq!(parse("\u{feff}{}").unwrap_err(), "data.json:1:1: Expected a value");
+        assert_eq!(parse("{1: 2}").unwrap_err(), "data.json:1:2: Expected '\"'");
+        assert_eq!(parse("[,]").unwrap_err(), "data.json:1:2: Expected a value");
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(parse(&nested(MAX_DEPTH + 1)).unwrap_err(), format!("data.json:1:{}: Too deeply nested", MAX_DEPTH + 1));
+        assert_eq!(parse(&"[{\"a\":".repeat(100_000)).unwrap_err(), format!("data.json:1:{}: Too deeply nested", 6 * 64 + 1));
+    }
+}
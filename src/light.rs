@@ -0,0 +1,108 @@
+use crate::float3::Float3;
+
+/// How the intensity of a light falls off with distance `d`, it is scaled by
+/// `1 / (constant + linear * d + quadratic * d^2)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn new(constant: f32, linear: f32, quadratic: f32) -> Self {
+        Attenuation { constant, linear, quadratic }
+    }
+
+    pub fn factor(&self, distance: f32) -> f32 {
+        1.0 / (self.constant + self.linear * distance + self.quadratic * distance * distance).max(f32::EPSILON)
+    }
+}
+
+/// A light source in world space. The colours include the light intensity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    /// Constant light reaching every surface from all directions.
+    Ambient { color: Float3 },
+    /// Parallel light, e.g. the sun, travelling along `direction`.
+    Directional { direction: Float3, color: Float3 },
+    /// Light emitted equally in all directions from a point.
+    Point { position: Float3, color: Float3, attenuation: Attenuation },
+    /// A point light restricted to a cone around `direction`. The light is at
+    /// full strength inside `inner_angle` and fades out towards `outer_angle`,
+    /// both given in degrees from the centre of the cone.
+    Spot {
+        position: Float3,
+        direction: Float3,
+        color: Float3,
+        attenuation: Attenuation,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+impl Light {
+    /// The light arriving at a world-space position, as the unit direction
+    /// towards the light and the colour it contributes. Ambient lights have no
+    /// direction and return `None`.
+    pub fn incoming(&self, position: Float3) -> Option<(Float3, Float3)> {
+        match *self {
+            Light::Ambient { .. } => None,
+            Light::Directional { direction, color } => Some(((-direction).normalize(), color)),
+            Light::Point { position: light_position, color, attenuation } => {
+                let to_light = light_position - position;
+                let distance = to_light.length();
+                Some((to_light.normalize(), color * attenuation.factor(distance)))
+            },
+            Light::Spot { position: light_position, direction, color, attenuation, inner_angle, outer_angle } => {
+                let to_light = light_position - position;
+                let distance = to_light.length();
+                let to_light = to_light.normalize();
+
+                // Fade smoothly between the inner and outer cone
+                let cos_angle = (-to_light).dot(&direction.normalize());
+                let cos_inner = inner_angle.to_radians().cos();
+                let cos_outer = outer_angle.to_radians().cos();
+                let t = ((cos_angle - cos_outer) / (cos_inner - cos_outer).max(f32::EPSILON)).clamp(0.0, 1.0);
+                let falloff = t * t * (3.0 - 2.0 * t);
+
+                Some((to_light, color * (attenuation.factor(distance) * falloff)))
+            },
+        }
+    }
+}
+
+/// The material properties at a single point of a surface, after texturing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surface {
+    pub diffuse: Float3,
+    pub specular: Float3,
+    pub shininess: f32,
+}
+
+/// Shade a surface point with Lambert diffuse and Blinn-Phong specular terms.
+///
+/// `normal` and `view_direction` (from the surface towards the eye) are
+/// expected to be unit vectors in world space.
+pub fn shade_blinn_phong(lights: &[Light], surface: &Surface, position: Float3, normal: Float3, view_direction: Float3) -> Float3 {
+    let mut color = Float3::zero();
+    for light in lights {
+        if let Light::Ambient { color: ambient } = light {
+            color = color + *ambient * surface.diffuse;
+            continue;
+        }
+
+        let Some((to_light, light_color)) = light.incoming(position) else {
+            continue;
+        };
+        let lambert = normal.dot(&to_light);
+        if lambert <= 0.0 {
+            continue; // Facing away from the light
+        }
+
+        let halfway = (to_light + view_direction).normalize();
+        let specular = normal.dot(&halfway).max(0.0).powf(surface.shininess);
+        color = color + light_color * (surface.diffuse * lambert + surface.specular * specular);
+    }
+    color
+}
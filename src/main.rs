@@ -1,48 +1,66 @@
 mod float2;
 mod float3;
+mod float4;
+mod interpolate;
 mod bitmap;
+mod y4m;
+mod png;
 mod triangle;
+mod triangulate;
+mod stl;
+mod ply;
+mod json;
+mod gltf;
 mod obj;
 mod render;
+mod raster;
+mod clip;
 mod transform;
+mod camera;
+mod controls;
+mod animation;
+mod timeline;
 mod asset;
+mod cli;
 mod scene;
+mod light;
+mod material;
+mod texture;
+mod shader;
+#[cfg(test)]
+mod temp_file;
 use std::{sync::Arc, time::{Instant}};
 
 use pixels::Pixels;
-use float3::Float3;
-use winit::{application::ApplicationHandler, dpi::{LogicalSize, Size}, event::WindowEvent, event_loop::{self, ActiveEventLoop}, window::{Window, WindowId}};
+use winit::{application::ApplicationHandler, dpi::{LogicalSize, Size}, event::{ElementState, WindowEvent}, event_loop::{self, ActiveEventLoop}, keyboard::{KeyCode, PhysicalKey}, window::{Window, WindowId}};
 
-use crate::{render::{Model, RenderTarget}};
+use crate::{animation::Animation, cli::CommonOptions, controls::{CameraControls, CameraMode}, float3::Float3, render::RenderTarget, scene::Scene};
 
-#[derive(Default)]
 pub struct App {
     window: Option<Arc<winit::window::Window>>,
     pixels: Option<Pixels<'static>>,
     animation: Animation,
+    controls: CameraControls,
     last_frame: Option<Instant>,
+    time: f32, // Position in the timeline in seconds
+    paused: bool, // Hold the models still to look at them
 }
 
-#[derive(Default)]
-struct Animation {
-    models: Vec<Model>,
-    total_duration: i32,
-    rotations: Vec<((i32, i32), (f32, f32))>,
-    render_target: RenderTarget,
-    start_time: Option<Instant>,
-    assets: asset::AssetLoader,
+// The title shows the camera mode, as C switches it without any other sign
+fn window_title(mode: CameraMode) -> String {
+    format!("Software Renderer - {:?} camera", mode)
 }
 
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &event_loop::ActiveEventLoop) {
-        let width = self.animation.render_target.width;
-        let height = self.animation.render_target.height;
+        let width = self.animation.render_target.width();
+        let height = self.animation.render_target.height();
         self.window = Some(Arc::new(event_loop.create_window(Window::default_attributes()
             .with_inner_size(Size::Logical(LogicalSize {
                 width: width as f64,
                 height: height as f64,
             }))
-            .with_title("Software Renderer".to_string())
+            .with_title(window_title(self.controls.mode))
             .with_window_icon(None))
             .expect("Failed to create window")));
 
@@ -50,56 +68,43 @@ impl ApplicationHandler for App {
         let window_size = self.window.as_ref().unwrap().inner_size();
         let surface_texture = pixels::SurfaceTexture::new(window_size.width, window_size.height, Arc::clone(self.window.as_ref().unwrap()));
         self.pixels = Some(Pixels::new(width as u32, height as u32, surface_texture).unwrap());
-        self.animation.start_time = Some(Instant::now());
         self.last_frame = Some(Instant::now());
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        let mode = self.controls.mode;
+        self.controls.handle_event(&event);
+        if let Some(window) = self.window.as_ref().filter(|_| self.controls.mode != mode) {
+            window.set_title(&window_title(self.controls.mode));
+        }
         match event {
+            winit::event::WindowEvent::KeyboardInput { event, .. }
+                if event.physical_key == PhysicalKey::Code(KeyCode::KeyP) && event.state == ElementState::Pressed && !event.repeat => {
+                self.paused = !self.paused;
+            },
             winit::event::WindowEvent::CloseRequested => {
                 println!("Window close requested, exiting application.");
                 event_loop.exit();
             },
             winit::event::WindowEvent::RedrawRequested => {    
-                // Update model transformations based on the current frame
+                // Advance by the time since the last frame
                 let now = Instant::now();
                 let elapsed = self.last_frame.map_or(0.0, |last| (now - last).as_secs_f32());
                 self.last_frame = Some(now);
-
-                let rotation_list = &self.animation.rotations;
-                let elapsed_time = self.animation.start_time
-                    .map_or(0, |start| start.elapsed().as_secs_f32() as i32) % self.animation.total_duration as i32;
-                let (rotation_yaw, rotation_pitch) = rotation_list.iter()
-                    .find(|&&((start, end), _)| {
-                        elapsed_time >= start && elapsed_time < end
-                    })
-                    .map(|&(_, rotation)| rotation)
-                    .unwrap_or((0.0, 0.0)); 
-
-                for model in self.animation.models.iter_mut() {
-                    model.transform.yaw += rotation_yaw * elapsed * 30.0; 
-                    model.transform.pitch += rotation_pitch * elapsed * 30.0; 
+                if !self.paused {
+                    self.time += elapsed;
                 }
+                self.animation.update(self.time);
+                self.controls.update(elapsed);
+                self.controls.place(&mut self.animation.scene.camera);
 
                 // Render the pixel in software to the render target
                 let animation = &mut self.animation;
-                animation.render_target.clear();
-                for model in animation.models.iter() {
-                    render::render(model, &mut animation.render_target);
-                }
+                animation.scene.draw(&mut animation.render_target, animation.unlit);
 
                 // Write the pixels to the pixel buffer used by the window
                 let frame = self.pixels.as_mut().unwrap().frame_mut();
-                let width = animation.render_target.width as usize;
-                for (y, row) in animation.render_target.pixels.iter().enumerate() {
-                    for (x, pixel) in row.iter().enumerate() {
-                        let index = (y * width + x) * 4;
-                        frame[index] = (pixel.r() * 255.0) as u8;
-                        frame[index + 1] = (pixel.g() * 255.0) as u8;
-                        frame[index + 2] = (pixel.b() * 255.0) as u8;
-                        frame[index + 3] = 255; // Alpha channel
-                    }
-                }
+                animation.render_target.copy_to_rgba8(frame);
 
                 self.pixels.as_mut().unwrap().render().expect("Failed to render pixels");
                 self.window.as_ref().unwrap().request_redraw();
@@ -110,7 +115,7 @@ impl ApplicationHandler for App {
                         return;
                     }
 
-                    if let Err(err) = pixels.resize_surface(size.width as u32, size.height as u32) {
+                    if let Err(err) = pixels.resize_surface(size.width, size.height) {
                         event_loop.exit();
                         eprintln!("Failed to resize surface: {}", err);
                         return;
@@ -123,11 +128,7 @@ impl ApplicationHandler for App {
                     }
                 }
 
-                let target = &mut self.animation.render_target;
-                target.width = size.width as usize;
-                target.height = size.height as usize;
-                target.pixels = vec![vec![Float3::zero(); target.width]; target.height];
-                target.depth_buffer = vec![vec![f32::INFINITY; target.width]; target.height];
+                self.animation.render_target.resize(size.width as usize, size.height as usize);
             },
             _ => {}
         }
@@ -137,54 +138,53 @@ impl ApplicationHandler for App {
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     use std::env;
     let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("render") => return cli::render_command(&args[2..]),
+        Some("export") => return cli::export_command(&args[2..]),
+        Some("convert") => return cli::convert_command(&args[2..]),
+        _ => {},
+    }
     if args.len() < 2 {
-        eprintln!("Usage: {} model number", args[0]);
+        eprintln!("Usage: {} model {}", args[0], cli::COMMON_USAGE);
+        eprintln!("       {} {} {}", args[0], cli::render_usage(), cli::COMMON_USAGE);
+        eprintln!("       {} {} {}", args[0], cli::export_usage(), cli::COMMON_USAGE);
+        eprintln!("       {} {} {}", args[0], cli::convert_usage(), cli::COMMON_USAGE);
         return Ok(());
     }
 
-    let event_loop = event_loop::EventLoop::new()?;
-    event_loop.set_control_flow(event_loop::ControlFlow::Poll);
-    let mut app = App::default();
-
-    let model_number: usize = args[1].parse().unwrap_or(0);
+    let mut common = CommonOptions::default();
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        if !common.parse(option, &mut options)? {
+            return Err(format!("Unknown option '{}'", option).into());
+        }
+    }
 
-    let assets = asset::AssetLoader::new();
-    let models = assets.get_models();
-    if models.len() == 0 {
+    let assets = asset::AssetLoader::new(common.load_options);
+    let loaded = assets.get_assets();
+    if loaded.is_empty() {
         return Err("No models loaded".into());
     }
 
-    if model_number >= models.len() {
-        eprintln!("Model number {} is out of range. Available models: 0 to {}", model_number, models.len() - 1);
-        return Ok(());
-    }
+    let asset = cli::select_asset(loaded, &args[1])?;
+    let models = asset.models(common.parts);
 
-    let mut model = models[model_number].clone();
-    model.transform.position.z = 5.0; // Move the model back in the Z direction
-    const VIDEO_DURATION : i32 = 30; // seconds
-    
-    let rotation_list = vec![
-        ((0,4), (0.04, 0.0)),
-        ((4,5), (0.0, 0.0)),
-        ((5,9), (0.0, 0.04)),
-        ((9, 10), (0.0, 0.0)),
-        ((10, 13), (0.06, 0.0)),
-        ((13, 17), (0.0, 0.06)),
-        ((17, 18), (0.0, 0.0)),
-        ((18, 20), (0.04, 0.0)),
-        ((20, 23), (0.0, 0.04)),
-        ((23, 25), (0.04, 0.0)),
-        ((25, 500), (0.0, 0.1))];
-    
-    app.animation = Animation {
-        models: vec![model.clone()],
-        total_duration: VIDEO_DURATION,
-        rotations: rotation_list.clone(),
-        render_target: RenderTarget::new(512, 512),
-        start_time: None,
-        assets: assets,
+    let lights = Scene::default_lights();
+    let mut render_target = RenderTarget::new(512, 512);
+    render_target.render_state = common.render_state;
+
+    let event_loop = event_loop::EventLoop::new()?;
+    event_loop.set_control_flow(event_loop::ControlFlow::Poll);
+    let animation = Animation {
+        scene: Scene { models, lights, camera: Scene::default_camera(5.0) },
+        unlit: common.unlit,
+        timeline: common.timeline()?,
+        render_target,
     };
-        
+    // Orbit around the models from where the default camera sees them
+    let controls = CameraControls::new(Float3::zero(), 5.0);
+    let mut app = App { window: None, pixels: None, animation, controls, last_frame: None, time: 0.0, paused: false };
+
     event_loop.run_app(&mut app)?;
     Ok(())
 }
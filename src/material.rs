@@ -0,0 +1,511 @@
+use std::{collections::HashMap, io::{BufRead, Write}, sync::Arc};
+
+use crate::{float2::Float2, float3::Float3, texture::{AddressMode, FilterMode, Texture, TextureCoordinate}};
+
+/// Surface properties used for shading, as described by an MTL material.
+///
+/// Besides the standard statements, the non-standard `texture_filter` statement
+/// selects how the texture maps of the material are filtered, e.g.
+/// `texture_filter bilinear`.
+///
+/// Properties marked as kept are not used for shading. They are only read by
+/// `write_library`, so materials keep them when written back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub ambient: Float3, // Ka, kept
+    pub diffuse: Float3, // Kd
+    pub specular: Float3, // Ks
+    pub emissive: Float3, // Ke
+    pub transmission_filter: Float3, // Tf, kept
+    pub shininess: f32, // Ns, the specular exponent
+    pub optical_density: f32, // Ni, the index of refraction, kept
+    pub dissolve: f32, // d, 1 is fully opaque
+    pub sharpness: f32, // sharpness, kept
+    pub illumination_model: u32, // illum
+    pub texture_filter: FilterMode,
+    pub ambient_map: Option<TextureMap>, // kept
+    pub diffuse_map: Option<TextureMap>,
+    pub specular_map: Option<TextureMap>,
+    pub emissive_map: Option<TextureMap>,
+    pub shininess_map: Option<TextureMap>, // kept
+    pub dissolve_map: Option<TextureMap>,
+    pub bump_map: Option<TextureMap>, // kept
+    pub displacement_map: Option<TextureMap>, // kept
+    pub decal_map: Option<TextureMap>, // kept
+    pub reflection_map: Option<TextureMap>, // kept
+}
+
+/// A texture bound to a material, with the options given in the MTL file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureMap {
+    pub texture: Arc<Texture>,
+    pub path: Option<String>, // The image the texture was loaded from, if any
+    pub options: TextureOptions,
+}
+
+/// The options of a texture map statement, e.g. `map_Kd -s 2 2 -clamp on file.bmp`.
+/// Like for materials, options marked as kept are only written back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureOptions {
+    pub offset: Float3, // -o
+    pub scale: Float3, // -s
+    pub turbulence: Float3, // -t, kept
+    pub address_mode: AddressMode, // -clamp
+    pub blend_u: bool, // -blendu, kept
+    pub blend_v: bool, // -blendv, kept
+    pub color_correction: bool, // -cc, kept
+    pub base: f32, // First value of -mm, kept
+    pub gain: f32, // Second value of -mm, kept
+    pub boost: f32, // -boost, kept
+    pub bump_multiplier: f32, // -bm, kept
+    pub channel: Option<char>, // -imfchan, the channel used for scalar maps
+    pub resolution: Option<u32>, // -texres, kept
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions {
+            offset: Float3::zero(),
+            scale: Float3::new(1.0, 1.0, 1.0),
+            turbulence: Float3::zero(),
+            address_mode: AddressMode::Wrap,
+            blend_u: true,
+            blend_v: true,
+            color_correction: false,
+            base: 0.0,
+            gain: 1.0,
+            boost: 0.0,
+            bump_multiplier: 1.0,
+            channel: None,
+            resolution: None,
+        }
+    }
+}
+
+impl TextureMap {
+    /// Sample the texture, applying the offset and scale of the map.
+    pub fn sample(&self, coordinate: &TextureCoordinate, filter: FilterMode) -> Float3 {
+        let options = &self.options;
+        let scale = Float2::new(options.scale.x, options.scale.y);
+        let coordinate = TextureCoordinate {
+            uv: coordinate.uv * scale + Float2::new(options.offset.x, options.offset.y),
+            ddx: coordinate.ddx * scale,
+            ddy: coordinate.ddy * scale,
+        };
+        self.texture.sample(&coordinate, options.address_mode, filter)
+    }
+
+    /// Sample a map holding a single value, e.g. a dissolve map, using the
+    /// channel selected by `-imfchan`. Defaults to the luminance.
+    pub fn sample_scalar(&self, coordinate: &TextureCoordinate, filter: FilterMode) -> f32 {
+        let color = self.sample(coordinate, filter);
+        match self.options.channel {
+            Some('r') => color.r(),
+            Some('g') => color.g(),
+            Some('b') => color.b(),
+            _ => color.dot(&Float3::new(0.2126, 0.7152, 0.0722)),
+        }
+    }
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Material {
+            name: name.to_string(),
+            ambient: Float3::zero(),
+            diffuse: Float3::new(0.8, 0.8, 0.8),
+            specular: Float3::zero(),
+            emissive: Float3::zero(),
+            transmission_filter: Float3::new(1.0, 1.0, 1.0),
+            shininess: 1.0,
+            optical_density: 1.0,
+            dissolve: 1.0,
+            sharpness: 60.0,
+            illumination_model: 2,
+            texture_filter: FilterMode::default(),
+            ambient_map: None,
+            diffuse_map: None,
+            specular_map: None,
+            emissive_map: None,
+            shininess_map: None,
+            dissolve_map: None,
+            bump_map: None,
+            displacement_map: None,
+            decal_map: None,
+            reflection_map: None,
+        }
+    }
+
+    /// Read all materials from an MTL file.
+    ///
+    /// Texture maps are loaded relative to the directory of the file, and a
+    /// texture that fails to load is reported and left out. Spectral colours
+    /// (`.rfl` files) are not supported and are skipped as well.
+    pub fn read_library(file_path: &str) -> Result<Vec<Material>, String> {
+        let file = std::fs::File::open(file_path).map_err(|e| format!("{}: {}", file_path, e))?;
+        let reader = std::io::BufReader::new(file);
+
+        // Several maps often share the same image, so only load it once
+        let mut textures: HashMap<String, Arc<Texture>> = HashMap::new();
+        let mut materials: Vec<Material> = Vec::new();
+        for (line_index, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| format!("{}: {}", file_path, e))?;
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.is_empty() || parts[0].starts_with('#') {
+                continue; // Skip empty lines and comments
+            }
+            let line_number = line_index + 1;
+            let context = |message: String| format!("{}:{}: {}", file_path, line_number, message);
+
+            if parts[0] == "newmtl" {
+                if parts.len() < 2 {
+                    return Err(context("Missing material name".to_string()));
+                }
+                materials.push(Material::new(&parts[1..].join(" ")));
+                continue;
+            }
+
+            let Some(material) = materials.last_mut() else {
+                return Err(context(format!("Statement '{}' before any newmtl", parts[0])));
+            };
+            let arguments = &parts[1..];
+            match parts[0] {
+                "Ka" | "Kd" | "Ks" | "Ke" | "Tf" => {
+                    let Some(color) = parse_color(arguments).map_err(context)? else {
+                        eprintln!("{}", context(format!("Spectral colours are not supported, ignoring {}", parts[0])));
+                        continue;
+                    };
+                    match parts[0] {
+                        "Ka" => material.ambient = color,
+                        "Kd" => material.diffuse = color,
+                        "Ks" => material.specular = color,
+                        "Ke" => material.emissive = color,
+                        _ => material.transmission_filter = color,
+                    }
+                },
+                "Ns" => material.shininess = parse_number(arguments, "specular exponent").map_err(context)?,
+                "Ni" => material.optical_density = parse_number(arguments, "optical density").map_err(context)?,
+                "d" => {
+                    // "d -halo factor" is not supported, the factor is used as is
+                    let arguments = arguments.strip_prefix(&["-halo"]).unwrap_or(arguments);
+                    material.dissolve = parse_number(arguments, "dissolve").map_err(context)?;
+                },
+                "Tr" => material.dissolve = 1.0 - parse_number::<f32>(arguments, "transparency").map_err(context)?,
+                "sharpness" => material.sharpness = parse_number(arguments, "sharpness").map_err(context)?,
+                "illum" => material.illumination_model = parse_number(arguments, "illumination model").map_err(context)?,
+                "texture_filter" => material.texture_filter = parse_number(arguments, "texture filter").map_err(context)?,
+                "map_Ka" | "map_Kd" | "map_Ks" | "map_Ke" | "map_Ns" | "map_d" | "map_bump" | "bump" | "disp" | "decal" | "refl" => {
+                    let Some(map) = parse_texture_map(arguments, file_path, &mut textures).map_err(context)? else {
+                        continue;
+                    };
+                    let slot = match parts[0] {
+                        "map_Ka" => &mut material.ambient_map,
+                        "map_Kd" => &mut material.diffuse_map,
+                        "map_Ks" => &mut material.specular_map,
+                        "map_Ke" => &mut material.emissive_map,
+                        "map_Ns" => &mut material.shininess_map,
+                        "map_d" => &mut material.dissolve_map,
+                        "map_bump" | "bump" => &mut material.bump_map,
+                        "disp" => &mut material.displacement_map,
+                        "decal" => &mut material.decal_map,
+                        _ => &mut material.reflection_map,
+                    };
+                    *slot = Some(map);
+                },
+                _ => eprintln!("{}", context(format!("Ignoring unsupported statement '{}'", parts[0]))),
+            }
+        }
+
+        Ok(materials)
+    }
+
+    /// Write materials to an MTL file.
+    ///
+    /// Texture maps refer to the images they were loaded from by a path relative
+    /// to the MTL file, so the files can be moved together. Maps that were not
+    /// loaded from an image are left out with a warning. The filter mode is only
+    /// written as a comment, as other tools do not know `texture_filter`.
+    pub fn write_library(materials: &[Material], file_path: &str) -> std::io::Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_path)?);
+        let directory = std::path::Path::new(file_path).parent().unwrap_or(std::path::Path::new(""));
+        for material in materials {
+            writeln!(writer, "newmtl {}", material.name)?;
+            let colors = [
+                ("Ka", material.ambient),
+                ("Kd", material.diffuse),
+                ("Ks", material.specular),
+                ("Ke", material.emissive),
+                ("Tf", material.transmission_filter),
+            ];
+            for (keyword, color) in colors {
+                writeln!(writer, "{} {} {} {}", keyword, color.r(), color.g(), color.b())?;
+            }
+            writeln!(writer, "Ns {}", material.shininess)?;
+            writeln!(writer, "Ni {}", material.optical_density)?;
+            writeln!(writer, "d {}", material.dissolve)?;
+            writeln!(writer, "sharpness {}", material.sharpness)?;
+            writeln!(writer, "illum {}", material.illumination_model)?;
+            if material.texture_filter != FilterMode::default() {
+                writeln!(writer, "# texture_filter {}", format!("{:?}", material.texture_filter).to_ascii_lowercase())?;
+            }
+
+            let maps = [
+                ("map_Ka", &material.ambient_map),
+                ("map_Kd", &material.diffuse_map),
+                ("map_Ks", &material.specular_map),
+                ("map_Ke", &material.emissive_map),
+                ("map_Ns", &material.shininess_map),
+                ("map_d", &material.dissolve_map),
+                ("map_bump", &material.bump_map),
+                ("disp", &material.displacement_map),
+                ("decal", &material.decal_map),
+                ("refl", &material.reflection_map),
+            ];
+            for (keyword, map) in maps {
+                let Some(map) = map else {
+                    continue;
+                };
+                let Some(path) = &map.path else {
+                    eprintln!("Leaving out {} of material {}, the texture was not loaded from a file", keyword, material.name);
+                    continue;
+                };
+                let path = relative_path(directory, std::path::Path::new(path)).unwrap_or_else(|| path.clone());
+                writeln!(writer, "{}{} {}", keyword, format_texture_options(&map.options), path)?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+}
+
+// The path of a file relative to a directory, with forward slashes. `None` if
+// either does not exist or they have no common root, e.g. are on other drives.
+fn relative_path(directory: &std::path::Path, file: &std::path::Path) -> Option<String> {
+    let directory = std::fs::canonicalize(if directory.as_os_str().is_empty() { std::path::Path::new(".") } else { directory }).ok()?;
+    let file = std::fs::canonicalize(file).ok()?;
+    let common = directory.components().zip(file.components()).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return None;
+    }
+    let up = directory.components().skip(common).map(|_| "..".to_string());
+    let down = file.components().skip(common).map(|component| component.as_os_str().to_string_lossy().into_owned());
+    Some(up.chain(down).collect::<Vec<_>>().join("/"))
+}
+
+// The options that differ from their defaults, each with a leading space
+fn format_texture_options(options: &TextureOptions) -> String {
+    let defaults = TextureOptions::default();
+    let mut result = String::new();
+    let switches = [
+        ("-blendu", options.blend_u, defaults.blend_u),
+        ("-blendv", options.blend_v, defaults.blend_v),
+        ("-cc", options.color_correction, defaults.color_correction),
+        ("-clamp", options.address_mode == AddressMode::Clamp, false),
+    ];
+    for (option, value, default) in switches {
+        if value != default {
+            result += &format!(" {} {}", option, if value { "on" } else { "off" });
+        }
+    }
+    let vectors = [("-o", options.offset, defaults.offset), ("-s", options.scale, defaults.scale), ("-t", options.turbulence, defaults.turbulence)];
+    for (option, value, default) in vectors {
+        if value != default {
+            result += &format!(" {} {} {} {}", option, value.x, value.y, value.z);
+        }
+    }
+    if (options.base, options.gain) != (defaults.base, defaults.gain) {
+        result += &format!(" -mm {} {}", options.base, options.gain);
+    }
+    if options.boost != defaults.boost {
+        result += &format!(" -boost {}", options.boost);
+    }
+    if options.bump_multiplier != defaults.bump_multiplier {
+        result += &format!(" -bm {}", options.bump_multiplier);
+    }
+    if let Some(channel) = options.channel {
+        result += &format!(" -imfchan {}", channel);
+    }
+    if let Some(resolution) = options.resolution {
+        result += &format!(" -texres {}", resolution);
+    }
+    result
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new("default")
+    }
+}
+
+// Parse "r [g b]" or "xyz x [y z]", a single value is used for all components.
+// Returns None for spectral colours.
+fn parse_color(arguments: &[&str]) -> Result<Option<Float3>, String> {
+    let arguments = match arguments.first() {
+        Some(&"spectral") => return Ok(None),
+        Some(&"xyz") => &arguments[1..],
+        _ => arguments,
+    };
+
+    let components = arguments.iter()
+        .map(|part| part.parse::<f32>().map_err(|e| format!("Invalid colour component '{}': {}", part, e)))
+        .collect::<Result<Vec<f32>, String>>()?;
+    match components[..] {
+        [value] => Ok(Some(Float3::new(value, value, value))),
+        [r, g, b] => Ok(Some(Float3::new(r, g, b))),
+        _ => Err(format!("Expected one or three colour components, got {}", components.len())),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(arguments: &[&str], name: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    let value = arguments.first().ok_or_else(|| format!("Missing {}", name))?;
+    value.parse().map_err(|e| format!("Invalid {} '{}': {}", name, value, e))
+}
+
+fn parse_switch(value: Option<&&str>, option: &str) -> Result<bool, String> {
+    match value {
+        Some(&"on") => Ok(true),
+        Some(&"off") => Ok(false),
+        _ => Err(format!("Expected on or off after {}", option)),
+    }
+}
+
+// Parse "[options] file" of a texture map statement and load the texture.
+// Returns None if the texture could not be loaded.
+fn parse_texture_map(arguments: &[&str], library_path: &str, textures: &mut HashMap<String, Arc<Texture>>) -> Result<Option<TextureMap>, String> {
+    let mut options = TextureOptions::default();
+    let mut i = 0;
+    while i < arguments.len() && arguments[i].starts_with('-') {
+        let option = arguments[i];
+        let value = arguments.get(i + 1);
+        i += 2;
+        match option {
+            "-blendu" => options.blend_u = parse_switch(value, option)?,
+            "-blendv" => options.blend_v = parse_switch(value, option)?,
+            "-cc" => options.color_correction = parse_switch(value, option)?,
+            "-clamp" => {
+                options.address_mode = if parse_switch(value, option)? { AddressMode::Clamp } else { AddressMode::Wrap };
+            },
+            "-bm" => options.bump_multiplier = parse_number(&arguments[i - 1..], "bump multiplier")?,
+            "-boost" => options.boost = parse_number(&arguments[i - 1..], "boost")?,
+            "-texres" => options.resolution = Some(parse_number(&arguments[i - 1..], "texture resolution")?),
+            "-imfchan" => options.channel = Some(parse_number(&arguments[i - 1..], "channel")?),
+            "-type" => {}, // Reflection map type, only sphere maps would be meaningful here
+            "-mm" => {
+                options.base = parse_number(&arguments[i - 1..], "base")?;
+                options.gain = parse_number(&arguments[i..], "gain")?;
+                i += 1;
+            },
+            "-o" | "-s" | "-t" => {
+                // One to three values, the missing ones keep their defaults
+                let mut values = match option {
+                    "-o" => options.offset,
+                    "-s" => options.scale,
+                    _ => options.turbulence,
+                };
+                let mut count = 0;
+                i -= 1;
+                while count < 3 && i < arguments.len() - 1 {
+                    let Ok(value) = arguments[i].parse::<f32>() else {
+                        break;
+                    };
+                    match count {
+                        0 => values.x = value,
+                        1 => values.y = value,
+                        _ => values.z = value,
+                    }
+                    count += 1;
+                    i += 1;
+                }
+                if count == 0 {
+                    return Err(format!("Expected a value after {}", option));
+                }
+                match option {
+                    "-o" => options.offset = values,
+                    "-s" => options.scale = values,
+                    _ => options.turbulence = values,
+                }
+            },
+            _ => return Err(format!("Unknown texture map option '{}'", option)),
+        }
+    }
+
+    if i >= arguments.len() {
+        return Err("Missing texture file name".to_string());
+    }
+
+    // Texture paths are relative to the material library
+    let file_name = arguments[i..].join(" ");
+    let texture_path = std::path::Path::new(library_path).with_file_name(&file_name).to_string_lossy().into_owned();
+    let path = Some(texture_path.clone());
+    let texture = match textures.get(&texture_path) {
+        Some(texture) => Arc::clone(texture),
+        None => match Texture::read_from_file(&texture_path) {
+            Ok(texture) => {
+                let texture = Arc::new(texture);
+                textures.insert(texture_path, Arc::clone(&texture));
+                texture
+            },
+            Err(e) => {
+                eprintln!("Failed to load texture: {}", e);
+                return Ok(None);
+            },
+        },
+    };
+    Ok(Some(TextureMap { texture, path, options }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_file::TempFile;
+
+    // Read `contents` as an MTL file, with the path taken off the front of errors
+    fn read(name: &str, contents: &[u8]) -> Result<Vec<Material>, String> {
+        let file = TempFile::new(&format!("{}.mtl", name), contents);
+        Material::read_library(&file.path).map_err(|e| e.replacen(&format!("{}:", file.path), "", 1))
+    }
+
+    #[test]
+    fn reads_colours_and_numbers() {
+        let materials = read("valid", b"# Comment\nnewmtl red paint\nKd 1 0 0\nKs 0.5\nNs 20\nd 0.5\n").unwrap();
+        assert_eq!(materials.len(), 1);
+        assert_eq!(materials[0].name, "red paint");
+        assert_eq!(materials[0].diffuse, Float3::new(1.0, 0.0, 0.0));
+        assert_eq!(materials[0].specular, Float3::new(0.5, 0.5, 0.5));
+        assert_eq!((materials[0].shininess, materials[0].dissolve), (20.0, 0.5));
+    }
+
+    #[test]
+    fn missing_arguments_report_their_line() {
+        let error = |contents: &[u8]| read("missing", contents).unwrap_err();
+        assert_eq!(error(b"newmtl\n"), "1: Missing material name");
+        assert_eq!(error(b"newmtl a\n\nKd 1 0\n"), "3: Expected one or three colour components, got 2");
+        assert_eq!(error(b"newmtl a\nNs\n"), "2: Missing specular exponent");
+        assert_eq!(error(b"newmtl a\nmap_Kd -clamp on\n"), "2: Missing texture file name");
+        assert_eq!(error(b"newmtl a\nmap_Kd -mm 0\n"), "2: Missing gain");
+        assert_eq!(error(b"newmtl a\nmap_Kd -s\n"), "2: Expected a value after -s");
+    }
+
+    #[test]
+    fn invalid_values_report_their_line() {
+        let error = |contents: &[u8]| read("invalid", contents).unwrap_err();
+        assert_eq!(error(b"Kd 1 1 1\n"), "1: Statement 'Kd' before any newmtl");
+        assert_eq!(error(b"newmtl a\nillum x\n"), "2: Invalid illumination model 'x': invalid digit found in string");
+        assert_eq!(error(b"newmtl a\nmap_Kd -clamp maybe file.png\n"), "2: Expected on or off after -clamp");
+    }
+
+    #[test]
+    fn binary_data_is_a_read_error() {
+        assert!(read("binary", &[b'n', 0xff, 0xfe, 0x00, b'\n']).unwrap_err().contains("valid UTF-8"));
+    }
+
+    #[test]
+    fn unsupported_statements_are_skipped() {
+        let materials = read("unsupported", b"newmtl a\nKd spectral file.rfl\nfoo 1 2\nKd 0 1 0\n").unwrap();
+        assert_eq!(materials[0].diffuse, Float3::new(0.0, 1.0, 0.0));
+    }
+}
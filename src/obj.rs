@@ -1,16 +1,22 @@
 
-use std::io::BufRead;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
 
 use crate::float3::Float3;
+use crate::material::Material;
+use crate::ply::Ply;
+use crate::render::Model;
+use crate::triangulate::polygon_normal;
 
 pub struct Vertex {
     pub position: Float3,
     pub scale: f32,
+    pub color: Option<Float3>, // From the common "v x y z r g b" extension
 }
 
 impl Vertex {
     pub fn new(position: Float3, scale: Option<f32>) -> Self {
-        Vertex { position, scale: scale.unwrap_or(1.0) }
+        Vertex { position, scale: scale.unwrap_or(1.0), color: None }
     }
     
 }
@@ -19,6 +25,28 @@ pub struct FaceElement {
     pub vertex_indices: Vec<usize>,
     pub texture_indices: Option<Vec<usize>>,
     pub normal_indices: Option<Vec<usize>>,
+    pub smoothing_group: u32, // 0 when smoothing is off
+    pub material: Option<usize>, // Index into the materials of the OBJ
+}
+
+/// A named part of an OBJ file, made of the faces that follow an `o` or `g`
+/// statement up to the next one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjObject {
+    pub name: String, // Set by o, empty before the first one
+    pub groups: Vec<String>, // Set by g, a face can belong to several groups at once
+    pub faces: std::ops::Range<usize>,
+}
+
+impl ObjObject {
+    /// The object name followed by the group names, e.g. `Car/wheels tyres`.
+    pub fn full_name(&self) -> String {
+        match (self.name.is_empty(), self.groups.is_empty()) {
+            (_, true) => self.name.clone(),
+            (true, false) => self.groups.join(" "),
+            (false, false) => format!("{}/{}", self.name, self.groups.join(" ")),
+        }
+    }
 }
 
 pub struct Obj {
@@ -26,7 +54,182 @@ pub struct Obj {
     pub texture_coordinates: Vec<Float3>,
     pub normals: Vec<Float3>,
     pub faces: Vec<FaceElement>,
-    pub name: String, 
+    pub objects: Vec<ObjObject>, // In file order, every face belongs to exactly one
+    pub material_libraries: Vec<String>, // Paths of MTL files, relative to the OBJ file
+    pub materials: Vec<Material>, // Every material used by a face, in order of first use
+}
+
+/// How `Obj::read_from_file` treats statements it does not support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Report unsupported statements and skip them.
+    #[default]
+    Lenient,
+    /// Fail on the first unsupported statement.
+    Strict,
+}
+
+/// An error while reading an OBJ file.
+#[derive(Debug)]
+pub enum ObjError {
+    /// The file could not be opened or read.
+    Io { path: String, error: std::io::Error },
+    /// The file was read, but a statement in it is malformed or unsupported.
+    Parse {
+        path: String,
+        line: usize,
+        column: usize, // 1-based character position of the token in the line
+        token: String,
+        kind: ObjErrorKind,
+    },
+}
+
+/// What is wrong with the token an `ObjError::Parse` points at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjErrorKind {
+    /// The statement ends before this argument, the token is the statement keyword.
+    MissingArgument(&'static str),
+    /// The token is not a valid number of this kind.
+    InvalidNumber(&'static str),
+    /// The token refers to an element that has not been defined.
+    IndexOutOfBounds(&'static str),
+    UnsupportedStatement,
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path, error),
+            ObjError::Parse { path, line, column, token, kind } => {
+                write!(f, "{}:{}:{}: ", path, line, column)?;
+                match kind {
+                    ObjErrorKind::MissingArgument(expected) => write!(f, "Missing {} in '{}' statement", expected, token),
+                    ObjErrorKind::InvalidNumber(expected) => write!(f, "Invalid {} '{}'", expected, token),
+                    ObjErrorKind::IndexOutOfBounds(expected) => write!(f, "{} '{}' is out of bounds", capitalize(expected), token),
+                    ObjErrorKind::UnsupportedStatement => write!(f, "Unsupported statement '{}'", token),
+                }
+            },
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { error, .. } => Some(error),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut characters = text.chars();
+    characters.next().map_or(String::new(), |first| first.to_uppercase().chain(characters).collect())
+}
+
+/// A single line of an OBJ file split into its keyword and arguments, which
+/// knows where its tokens are for error reporting.
+struct Statement<'a> {
+    path: &'a str,
+    line_number: usize,
+    line: &'a str,
+    tokens: Vec<&'a str>, // The keyword followed by the arguments
+}
+
+impl<'a> Statement<'a> {
+    fn new(path: &'a str, line_number: usize, line: &'a str) -> Self {
+        let line = line.split('#').next().unwrap_or_default(); // Strip comments
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        Statement { path, line_number, line, tokens }
+    }
+
+    fn keyword(&self) -> Option<&'a str> {
+        self.tokens.first().copied()
+    }
+
+    fn arguments(&self) -> &[&'a str] {
+        self.tokens.get(1..).unwrap_or_default()
+    }
+
+    // Build an error pointing at `token`, which must be a slice of the line
+    fn error(&self, token: &str, kind: ObjErrorKind) -> ObjError {
+        let offset = (token.as_ptr() as usize).saturating_sub(self.line.as_ptr() as usize).min(self.line.len());
+        ObjError::Parse {
+            path: self.path.to_string(),
+            line: self.line_number,
+            column: self.line[..offset].chars().count() + 1,
+            token: token.to_string(),
+            kind,
+        }
+    }
+
+    fn argument(&self, index: usize, expected: &'static str) -> Result<&'a str, ObjError> {
+        self.arguments().get(index).copied()
+            .ok_or_else(|| self.error(self.tokens[0], ObjErrorKind::MissingArgument(expected)))
+    }
+
+    fn number<T: std::str::FromStr>(&self, index: usize, expected: &'static str) -> Result<T, ObjError> {
+        let token = self.argument(index, expected)?;
+        token.parse().map_err(|_| self.error(token, ObjErrorKind::InvalidNumber(expected)))
+    }
+
+    fn optional_number<T: std::str::FromStr>(&self, index: usize, expected: &'static str) -> Result<Option<T>, ObjError> {
+        match self.arguments().get(index) {
+            Some(_) => self.number(index, expected).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // Resolve one of the indices of a face vertex like `1/2/3` to a 0-based
+    // index, where `count` elements have been defined so far. Returns `None`
+    // if the index is left out.
+    //
+    // Positive indices count from the start of the file and may refer to
+    // elements defined further down, so those past the end are recorded in
+    // `forward_references` and checked once the whole file is read. Negative
+    // indices count back from the last element defined so far.
+    fn index(&self, token: Option<&str>, element: Element, count: usize, forward_references: &mut Vec<ForwardReference>) -> Result<Option<usize>, ObjError> {
+        let Some(token) = token.filter(|token| !token.is_empty()) else {
+            return Ok(None);
+        };
+        let index: i64 = token.parse().map_err(|_| self.error(token, ObjErrorKind::InvalidNumber(element.name())))?;
+        let out_of_bounds = || self.error(token, ObjErrorKind::IndexOutOfBounds(element.name()));
+        let index = match index {
+            1.. => (index - 1) as usize,
+            0 => return Err(out_of_bounds()),
+            _ => count.checked_sub(index.unsigned_abs() as usize).ok_or_else(out_of_bounds)?,
+        };
+        if index >= count {
+            forward_references.push(ForwardReference { element, index, error: out_of_bounds() });
+        }
+        Ok(Some(index))
+    }
+}
+
+/// The kinds of elements a face refers to by index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Element {
+    Vertex,
+    TextureCoordinate,
+    Normal,
+}
+
+impl Element {
+    fn name(&self) -> &'static str {
+        match self {
+            Element::Vertex => "vertex index",
+            Element::TextureCoordinate => "texture index",
+            Element::Normal => "normal index",
+        }
+    }
+}
+
+/// An index to an element that was not defined yet when the face using it was
+/// read, with the error to report if it is never defined.
+struct ForwardReference {
+    element: Element,
+    index: usize,
+    error: ObjError,
 }
 
 impl Obj {
@@ -36,7 +239,9 @@ impl Obj {
             texture_coordinates: Vec::new(),
             normals: Vec::new(),
             faces: Vec::new(),
-            name: String::new(), 
+            objects: Vec::new(),
+            material_libraries: Vec::new(),
+            materials: Vec::new(),
         }
     }
 
@@ -61,133 +266,509 @@ impl Obj {
             vertex_indices,
             texture_indices,
             normal_indices,
+            smoothing_group: 0,
+            material: None,
         };
 
         self.faces.push(face);
+        if self.objects.is_empty() {
+            self.start_object(String::new(), Vec::new());
+        }
+        let object = self.objects.last_mut().unwrap();
+        object.faces.end = self.faces.len();
         self.faces.len() - 1 // Return the index of the new face
     }
 
-    pub fn clear(&mut self) {
-        self.vertices.clear();
-        self.texture_coordinates.clear();
-        self.normals.clear();
-        self.faces.clear();
+    /// Start a new object or group that the following faces are added to.
+    pub fn start_object(&mut self, name: String, groups: Vec<String>) {
+        let start = self.faces.len();
+        match self.objects.last_mut() {
+            // Replace objects without any faces, e.g. an o followed by a g
+            Some(object) if object.faces.is_empty() => *object = ObjObject { name, groups, faces: start..start },
+            _ => self.objects.push(ObjObject { name, groups, faces: start..start }),
+        }
     }
 
-    pub fn read_from_file(file_path: &str) -> Result<Self, String> {
+    /// Read an OBJ file.
+    ///
+    /// Malformed statements are always an error. Statements this reader does
+    /// not support, e.g. curves, either fail in `ParseMode::Strict` or are
+    /// reported once per keyword and skipped in `ParseMode::Lenient`.
+    ///
+    /// Faces may use negative indices relative to the elements defined so far,
+    /// and positive indices to elements defined later in the file. Indices are
+    /// validated after the whole file is read.
+    pub fn read_from_file(file_path: &str, mode: ParseMode) -> Result<Self, ObjError> {
         let mut result = Self::new();
 
-        let file = std::fs::File::open(file_path).map_err(|e| format!("Failed to open file: {}", e))?;
+        let io_error = |error| ObjError::Io { path: file_path.to_string(), error };
+        let file = std::fs::File::open(file_path).map_err(io_error)?;
         let mut reader = std::io::BufReader::new(file);
 
+        let mut smoothing_group = 0;
+        let mut material = None;
+        let mut skipped_keywords = std::collections::HashSet::new();
+        let mut forward_references = Vec::new();
         let mut line = String::new();
-        while (reader.read_line(&mut line).map_err(|e| format!("Failed to read from file: {}", e))?) > 0 {
-            line = line.trim().to_string();
- 
-            if line.is_empty() || line.starts_with('#') {
+        let mut line_number = 0;
+        while reader.read_line(&mut line).map_err(io_error)? > 0 {
+            line_number += 1;
+            let statement = Statement::new(file_path, line_number, &line);
+            let Some(keyword) = statement.keyword() else {
                 line.clear(); // Clear the line for the next iteration
                 continue; // Skip empty lines and comments
-            }
+            };
 
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            match parts[0] {
+            match keyword {
                 "o" => {
-                    if parts.len() > 1 {
-                        result.name = parts[1].to_string();
-                    } 
+                    // A new object starts without any groups
+                    result.start_object(statement.arguments().join(" "), Vec::new());
+                },
+                "g" => {
+                    let name = result.objects.last().map(|object| object.name.clone()).unwrap_or_default();
+                    let mut groups: Vec<String> = statement.arguments().iter().map(|group| group.to_string()).collect();
+                    if groups.is_empty() {
+                        groups.push("default".to_string());
+                    }
+                    result.start_object(name, groups);
                 },
                 "v" => {
                     let position = Float3::new(
-                        parts[1].parse().map_err(|e| format!("Invalid vertex position: {}", e))?,
-                        parts[2].parse().map_err(|e| format!("Invalid vertex position: {}", e))?,
-                        parts[3].parse().map_err(|e| format!("Invalid vertex position: {}", e))?,
+                        statement.number(0, "vertex position")?,
+                        statement.number(1, "vertex position")?,
+                        statement.number(2, "vertex position")?,
                     );
-
-                    if parts.len() == 5 {
-                        let scale = parts[4].parse().map_err(|e| format!("Invalid vertex scale: {}", e))?;
-                        result.add_vertex(position, Some(scale));
+                    // Either a weight or, as an extension, a colour follows the position
+                    if statement.arguments().len() >= 6 {
+                        let vertex = result.add_vertex(position, None);
+                        result.vertices[vertex].color = Some(Float3::new(
+                            statement.number(3, "vertex color")?,
+                            statement.number(4, "vertex color")?,
+                            statement.number(5, "vertex color")?,
+                        ));
                     } else {
-                        result.add_vertex(position, None);
+                        let scale = statement.optional_number(3, "vertex scale")?;
+                        result.add_vertex(position, scale);
                     }
                 },
                 "vn" => {
                     let normal = Float3::new(
-                        parts[1].parse().map_err(|e| format!("Invalid normal vector: {}", e))?,
-                        parts[2].parse().map_err(|e| format!("Invalid normal vector: {}", e))?,
-                        parts[3].parse().map_err(|e| format!("Invalid normal vector: {}", e))?,
+                        statement.number(0, "normal vector")?,
+                        statement.number(1, "normal vector")?,
+                        statement.number(2, "normal vector")?,
                     );
                     result.add_normal(normal);
                 },
                 "vt" => {
+                    // Only u is required, v and w default to 0
                     let texture_coordinate = Float3::new(
-                        parts[1].parse().map_err(|e| format!("Invalid texture coordinate: {}", e))?,
-                        parts[2].parse().map_err(|e| format!("Invalid texture coordinate: {}", e))?,
-                        if parts.len() > 3 {
-                            parts[3].parse().map_err(|e| format!("Invalid texture coordinate: {}", e))?
-                        } else {
-                            0.0 // Default z-coordinate if not provided
-                        },
+                        statement.number(0, "texture coordinate")?,
+                        statement.optional_number(1, "texture coordinate")?.unwrap_or(0.0),
+                        statement.optional_number(2, "texture coordinate")?.unwrap_or(0.0),
                     );
                     result.add_texture_coordinate(texture_coordinate);
                 },
                 "s" => {
-                    // Smoothing group, can be ignored for now
+                    smoothing_group = match statement.arguments().first() {
+                        Some(&"off") | None => 0,
+                        Some(_) => statement.number(0, "smoothing group")?,
+                    };
                 },
                 "mtllib" => {
-                    // Material library, can be ignored for now
+                    statement.argument(0, "material library")?;
+                    result.material_libraries.extend(statement.arguments().iter().map(|library| library.to_string()));
                 },
                 "usemtl" => {
-                    // Material usage, can be ignored for now
+                    // The materials are looked up in the libraries once the whole file is read
+                    statement.argument(0, "material name")?;
+                    let name = statement.arguments().join(" ");
+                    material = Some(match result.materials.iter().position(|material| material.name == name) {
+                        Some(index) => index,
+                        None => {
+                            result.materials.push(Material::new(&name));
+                            result.materials.len() - 1
+                        },
+                    });
                 },
                 "f" => {
+                    statement.argument(2, "face vertex")?; // A face needs at least three vertices
                     let mut vertex_indices = Vec::new();
                     let mut texture_indices = None;
                     let mut normal_indices = None;
 
-                    for part in &parts[1..] {
-                        let indices: Vec<&str> = part.split('/').collect();
-                        if indices.len() > 0 {
-                            let vertex_index = indices[0].parse::<usize>().map_err(|e| format!("Invalid vertex index: {}", e))? - 1; // OBJ indices are 1-based
-                            vertex_indices.push(vertex_index);
-                            if vertex_index >= result.vertices.len() {
-                                return Err(format!("Vertex index {} out of bounds", vertex_index + 1));
-                            }
-
-                            if indices.len() > 1 && !indices[1].is_empty() {
-                                if texture_indices.is_none() {
-                                    texture_indices = Some(Vec::new());
-                                }
-                                let texture_index = indices[1].parse::<usize>().map_err(|e| format!("Invalid texture index: {}", e))? - 1;
-                                if texture_index >= result.texture_coordinates.len() {
-                                    return Err(format!("Texture index {} out of bounds", texture_index + 1));
-                                }
-
-                                texture_indices.as_mut().unwrap().push(texture_index);
-                            }
-
-                            if indices.len() > 2 && !indices[2].is_empty() {
-                                if normal_indices.is_none() {
-                                    normal_indices = Some(Vec::new());
-                                }
-                                let normal_index = indices[2].parse::<usize>().map_err(|e| format!("Invalid normal index: {}", e))? - 1;
-                                if normal_index >= result.normals.len() {
-                                    return Err(format!("Normal index {} out of bounds", normal_index + 1));
-                                }
-
-                                normal_indices.as_mut().unwrap().push(normal_index);
-                            }
+                    for &part in statement.arguments().iter() {
+                        let mut indices = part.split('/');
+                        let vertex_index = statement.index(indices.next(), Element::Vertex, result.vertices.len(), &mut forward_references)?;
+                        vertex_indices.push(vertex_index.ok_or_else(|| statement.error(part, ObjErrorKind::MissingArgument("vertex index")))?);
+
+                        if let Some(texture_index) = statement.index(indices.next(), Element::TextureCoordinate, result.texture_coordinates.len(), &mut forward_references)? {
+                            texture_indices.get_or_insert_with(Vec::new).push(texture_index);
+                        }
+
+                        if let Some(normal_index) = statement.index(indices.next(), Element::Normal, result.normals.len(), &mut forward_references)? {
+                            normal_indices.get_or_insert_with(Vec::new).push(normal_index);
                         }
                     }
 
-                    result.add_face(vertex_indices, texture_indices, normal_indices);
+                    let face = result.add_face(vertex_indices, texture_indices, normal_indices);
+                    result.faces[face].smoothing_group = smoothing_group;
+                    result.faces[face].material = material;
+                },
+                _ => match mode {
+                    ParseMode::Strict => return Err(statement.error(keyword, ObjErrorKind::UnsupportedStatement)),
+                    ParseMode::Lenient => {
+                        if skipped_keywords.insert(keyword.to_string()) {
+                            eprintln!("{}, skipping every '{}' statement", statement.error(keyword, ObjErrorKind::UnsupportedStatement), keyword);
+                        }
+                    },
                 },
-                _ => { todo!("Handle other OBJ commands like vt, vn, f, etc.") },
             }
 
             line.clear(); // Clear the line for the next iteration
         }
 
+        for reference in forward_references {
+            let count = match reference.element {
+                Element::Vertex => result.vertices.len(),
+                Element::TextureCoordinate => result.texture_coordinates.len(),
+                Element::Normal => result.normals.len(),
+            };
+            if reference.index >= count {
+                return Err(reference.error);
+            }
+        }
+
+        result.resolve_materials(file_path);
+        Ok(result)
+    }
+
+    /// Write the OBJ to a file, along with an MTL file next to it with the
+    /// same name if it has any materials.
+    pub fn write_to_file(&self, file_path: &str) -> std::io::Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_path)?);
+        if !self.materials.is_empty() {
+            let library_path = std::path::Path::new(file_path).with_extension("mtl");
+            Material::write_library(&self.materials, &library_path.to_string_lossy())?;
+            let library_name = library_path.file_name().unwrap_or_default().to_string_lossy();
+            writeln!(writer, "mtllib {}", library_name)?;
+        }
+
+        for vertex in self.vertices.iter() {
+            let p = vertex.position;
+            match vertex.color {
+                Some(c) => writeln!(writer, "v {} {} {} {} {} {}", p.x, p.y, p.z, c.r(), c.g(), c.b())?,
+                None if vertex.scale == 1.0 => writeln!(writer, "v {} {} {}", p.x, p.y, p.z)?,
+                None => writeln!(writer, "v {} {} {} {}", p.x, p.y, p.z, vertex.scale)?,
+            }
+        }
+        for uv in self.texture_coordinates.iter() {
+            if uv.z == 0.0 {
+                writeln!(writer, "vt {} {}", uv.x, uv.y)?;
+            } else {
+                writeln!(writer, "vt {} {} {}", uv.x, uv.y, uv.z)?;
+            }
+        }
+        for n in self.normals.iter() {
+            writeln!(writer, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+
+        let whole_file = [ObjObject { name: String::new(), groups: Vec::new(), faces: 0..self.faces.len() }];
+        let objects = if self.objects.is_empty() { &whole_file[..] } else { &self.objects[..] };
+        let mut name = "";
+        let mut smoothing_group = 0;
+        let mut material = None;
+        for object in objects {
+            if object.name != name {
+                name = &object.name;
+                writeln!(writer, "o {}", name)?;
+            }
+            if !object.groups.is_empty() {
+                writeln!(writer, "g {}", object.groups.join(" "))?;
+            }
+
+            for face in self.faces[object.faces.clone()].iter() {
+                if face.smoothing_group != smoothing_group {
+                    smoothing_group = face.smoothing_group;
+                    match smoothing_group {
+                        0 => writeln!(writer, "s off")?,
+                        group => writeln!(writer, "s {}", group)?,
+                    }
+                }
+                // Faces can not go back to having no material, so they keep the last one
+                if let Some(index) = face.material.filter(|_| face.material != material) {
+                    material = face.material;
+                    writeln!(writer, "usemtl {}", self.materials[index].name)?;
+                }
+
+                write!(writer, "f")?;
+                for (i, vertex_index) in face.vertex_indices.iter().enumerate() {
+                    // Indices are 1-based, with the texture index left empty if only the normal is given
+                    let texture_index = face.texture_indices.as_ref().and_then(|indices| indices.get(i));
+                    let normal_index = face.normal_indices.as_ref().and_then(|indices| indices.get(i));
+                    match (texture_index, normal_index) {
+                        (None, None) => write!(writer, " {}", vertex_index + 1)?,
+                        (Some(t), None) => write!(writer, " {}/{}", vertex_index + 1, t + 1)?,
+                        (None, Some(n)) => write!(writer, " {}//{}", vertex_index + 1, n + 1)?,
+                        (Some(t), Some(n)) => write!(writer, " {}/{}/{}", vertex_index + 1, t + 1, n + 1)?,
+                    }
+                }
+                writeln!(writer)?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// Convert models into an OBJ, with one object for every model.
+    ///
+    /// Positions and normals are placed by the transform of each model. They
+    /// are shared between faces where they are identical, as are texture
+    /// coordinates. Materials are
+    /// matched by name, so materials with the same name are written once.
+    pub fn from_models(models: &[Model]) -> Self {
+        let mut result = Self::new();
+        let mut positions = HashMap::new();
+        let mut uvs = HashMap::new();
+        let mut normals = HashMap::new();
+        let key = |v: Float3| [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()];
+
+        for model in models {
+            result.start_object(model.name.clone(), Vec::new());
+            for triangle in model.world_triangles() {
+                let corners = [triangle.a, triangle.b, triangle.c];
+                let mut vertex_indices = Vec::with_capacity(3);
+                let mut texture_indices = Vec::with_capacity(3);
+                let mut normal_indices = Vec::with_capacity(3);
+                for (i, corner) in corners.into_iter().enumerate() {
+                    let uv = Float3::new(triangle.uvs[i].x, triangle.uvs[i].y, 0.0);
+                    vertex_indices.push(*positions.entry(key(corner)).or_insert_with(|| result.add_vertex(corner, None)));
+                    texture_indices.push(*uvs.entry(key(uv)).or_insert_with(|| result.add_texture_coordinate(uv)));
+                    normal_indices.push(*normals.entry(key(triangle.normals[i])).or_insert_with(|| result.add_normal(triangle.normals[i])));
+                }
+
+                let material = &model.materials[triangle.material];
+                let material_index = match result.materials.iter().position(|existing| existing.name == material.name) {
+                    Some(index) => index,
+                    None => {
+                        result.materials.push(material.clone());
+                        result.materials.len() - 1
+                    },
+                };
+                let face = result.add_face(vertex_indices, Some(texture_indices), Some(normal_indices));
+                result.faces[face].material = Some(material_index);
+            }
+        }
+        result
+    }
+
+    /// Convert a PLY file into an OBJ.
+    ///
+    /// Positions, normals, texture coordinates and colours are taken from the
+    /// vertex properties with their common names, other properties are ignored.
+    /// Faces without normals are put in a smoothing group, as PLY files are
+    /// usually scans of smooth surfaces.
+    pub fn from_ply(ply: &Ply) -> Result<Self, String> {
+        let vertex = ply.element("vertex").ok_or("Missing vertex element")?;
+        let scalar = |names: &[&str]| vertex.scalar(names);
+        let (Some((x, _)), Some((y, _)), Some((z, _))) = (scalar(&["x"]), scalar(&["y"]), scalar(&["z"])) else {
+            return Err("Missing vertex positions".to_string());
+        };
+        let normals = scalar(&["nx"]).zip(scalar(&["ny"])).zip(scalar(&["nz"]));
+        let uvs = scalar(&["u", "s", "texture_u", "texture_s"]).zip(scalar(&["v", "t", "texture_v", "texture_t"]));
+        let colors = scalar(&["red", "r", "diffuse_red"]).zip(scalar(&["green", "g", "diffuse_green"])).zip(scalar(&["blue", "b", "diffuse_blue"]));
+
+        let mut result = Self::new();
+        for i in 0..vertex.count {
+            let index = result.add_vertex(Float3::new(x[i] as f32, y[i] as f32, z[i] as f32), None);
+            if let Some((((red, red_type), (green, green_type)), (blue, blue_type))) = colors {
+                result.vertices[index].color = Some(Float3::new(
+                    (red[i] / red_type.max_intensity()) as f32,
+                    (green[i] / green_type.max_intensity()) as f32,
+                    (blue[i] / blue_type.max_intensity()) as f32,
+                ));
+            }
+            if let Some((((nx, _), (ny, _)), (nz, _))) = normals {
+                result.add_normal(Float3::new(nx[i] as f32, ny[i] as f32, nz[i] as f32));
+            }
+            if let Some(((u, _), (v, _))) = uvs {
+                result.add_texture_coordinate(Float3::new(u[i] as f32, v[i] as f32, 0.0));
+            }
+        }
+
+        // Point clouds have no faces at all
+        let faces = ply.element("face").and_then(|face| face.list(&["vertex_indices", "vertex_index"])).unwrap_or_default();
+        for (face_index, indices) in faces.iter().enumerate() {
+            let indices = indices.iter()
+                .map(|&index| if index >= 0.0 && (index as usize) < vertex.count { Ok(index as usize) } else { Err(index) })
+                .collect::<Result<Vec<usize>, f64>>()
+                .map_err(|index| format!("Vertex index {} of face {} is out of bounds", index, face_index))?;
+            let face = result.add_face(indices.clone(), uvs.map(|_| indices.clone()), normals.map(|_| indices));
+            result.faces[face].smoothing_group = 1;
+        }
         Ok(result)
     }
 
-}
\ No newline at end of file
+    // Replace the placeholder materials named by usemtl with their definitions
+    // from the material libraries. Missing libraries or materials are reported
+    // and keep the default material properties.
+    fn resolve_materials(&mut self, file_path: &str) {
+        let mut definitions = Vec::new();
+        for library in self.material_libraries.iter() {
+            let library_path = std::path::Path::new(file_path).with_file_name(library);
+            match Material::read_library(&library_path.to_string_lossy()) {
+                Ok(materials) => definitions.extend(materials),
+                Err(e) => eprintln!("Failed to read material library: {}", e),
+            }
+        }
+
+        for material in self.materials.iter_mut() {
+            match definitions.iter().find(|definition| definition.name == material.name) {
+                Some(definition) => *material = definition.clone(),
+                None => eprintln!("Material {} used by {} is not defined", material.name, file_path),
+            }
+        }
+    }
+
+
+    /// Generate vertex normals for all faces that have none.
+    ///
+    /// Faces with smoothing off get their flat face normal. Within a smoothing
+    /// group, each vertex gets the average of the normals of the faces around
+    /// it, weighted by face area and by the angle of the face at that corner.
+    pub fn generate_normals(&mut self) {
+        let mut smooth_normals: HashMap<(usize, u32), Float3> = HashMap::new();
+        for face in self.faces.iter().filter(|face| face.normal_indices.is_none() && face.smoothing_group != 0) {
+            let face_normal = self.face_normal(face); // Length is twice the area of the face
+            let count = face.vertex_indices.len();
+            for i in 0..count {
+                let vertex = self.vertices[face.vertex_indices[i]].position;
+                let previous = self.vertices[face.vertex_indices[(i + count - 1) % count]].position;
+                let next = self.vertices[face.vertex_indices[(i + 1) % count]].position;
+                let cos_angle = (previous - vertex).normalize().dot(&(next - vertex).normalize());
+                let weighted = face_normal * cos_angle.clamp(-1.0, 1.0).acos();
+
+                let normal = smooth_normals.entry((face.vertex_indices[i], face.smoothing_group)).or_insert(Float3::zero());
+                *normal = *normal + weighted;
+            }
+        }
+
+        let mut smooth_indices = HashMap::new();
+        for face_index in 0..self.faces.len() {
+            let face = &self.faces[face_index];
+            if face.normal_indices.is_some() {
+                continue;
+            }
+
+            let group = face.smoothing_group;
+            let vertex_indices = face.vertex_indices.clone();
+            let normal_indices = if group == 0 {
+                let normal = self.add_normal(self.face_normal(face).normalize());
+                vec![normal; vertex_indices.len()]
+            } else {
+                vertex_indices.into_iter().map(|vertex| {
+                    *smooth_indices.entry((vertex, group)).or_insert_with(|| {
+                        self.add_normal(smooth_normals[&(vertex, group)].normalize())
+                    })
+                }).collect()
+            };
+            self.faces[face_index].normal_indices = Some(normal_indices);
+        }
+    }
+
+    // Normal of a polygon using Newell's method, its length is twice the area of the polygon
+    fn face_normal(&self, face: &FaceElement) -> Float3 {
+        let positions: Vec<Float3> = face.vertex_indices.iter().map(|&i| self.vertices[i].position).collect();
+        polygon_normal(&positions)
+    }
+
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_file::TempFile;
+
+    fn read(name: &str, contents: &[u8], mode: ParseMode) -> Result<Obj, ObjError> {
+        let file = TempFile::new(&format!("{}.obj", name), contents);
+        Obj::read_from_file(&file.path, mode)
+    }
+
+    // The line, column and kind of a parse error
+    fn parse_error(result: Result<Obj, ObjError>) -> (usize, usize, ObjErrorKind) {
+        match result {
+            Err(ObjError::Parse { line, column, kind, .. }) => (line, column, kind),
+            Err(error) => panic!("Expected a parse error, got {}", error),
+            Ok(_) => panic!("Expected a parse error"),
+        }
+    }
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+    #[test]
+    fn index_zero_is_out_of_bounds() {
+        let result = read("zero", format!("{}f 1 0 3\n", TRIANGLE).as_bytes(), ParseMode::Lenient);
+        assert_eq!(parse_error(result), (4, 5, ObjErrorKind::IndexOutOfBounds("vertex index")));
+    }
+
+    #[test]
+    fn negative_index_before_the_first_element() {
+        let result = read("negative", format!("{}f -1 -2 -99\n", TRIANGLE).as_bytes(), ParseMode::Lenient);
+        assert_eq!(parse_error(result), (4, 9, ObjErrorKind::IndexOutOfBounds("vertex index")));
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_element() {
+        let obj = read("relative", format!("{}f -3 -2 -1\n", TRIANGLE).as_bytes(), ParseMode::Strict).unwrap();
+        assert_eq!(obj.faces[0].vertex_indices, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn forward_references_are_resolved_at_the_end() {
+        let obj = read("forward", format!("f 1 2 3\n{}", TRIANGLE).as_bytes(), ParseMode::Strict).unwrap();
+        assert_eq!(obj.faces[0].vertex_indices, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn undefined_forward_reference_points_at_the_face() {
+        let result = read("undefined", format!("f 1 2 4\n{}", TRIANGLE).as_bytes(), ParseMode::Lenient);
+        assert_eq!(parse_error(result), (1, 7, ObjErrorKind::IndexOutOfBounds("vertex index")));
+    }
+
+    #[test]
+    fn texture_index_error_points_inside_the_face_vertex() {
+        let result = read("texture", format!("{}vt 0 0\nf 1/1 2/2 3/1\n", TRIANGLE).as_bytes(), ParseMode::Lenient);
+        assert_eq!(parse_error(result), (5, 9, ObjErrorKind::IndexOutOfBounds("texture index")));
+    }
+
+    #[test]
+    fn missing_arguments_point_at_the_statement() {
+        // Missing arguments point at the keyword of the statement
+        let result = read("vertex", b"v 0 0 0\nv 1.0 2.0", ParseMode::Lenient);
+        assert_eq!(parse_error(result), (2, 1, ObjErrorKind::MissingArgument("vertex position")));
+        let result = read("face", format!("{}f 1 2 3\n f 1 2", TRIANGLE).as_bytes(), ParseMode::Lenient);
+        assert_eq!(parse_error(result), (5, 2, ObjErrorKind::MissingArgument("face vertex")));
+        let result = read("empty_index", format!("{}f 1 / 3\n", TRIANGLE).as_bytes(), ParseMode::Lenient);
+        assert_eq!(parse_error(result), (4, 5, ObjErrorKind::MissingArgument("vertex index")));
+    }
+
+    #[test]
+    fn invalid_numbers_point_at_the_token() {
+        let result = read("number", b"v 0 0 0\nv 1.0 2.0 abc\n", ParseMode::Lenient);
+        assert_eq!(parse_error(result), (2, 11, ObjErrorKind::InvalidNumber("vertex position")));
+        let result = read("index", format!("{}f 1 x 3\n", TRIANGLE).as_bytes(), ParseMode::Lenient);
+        assert_eq!(parse_error(result), (4, 5, ObjErrorKind::InvalidNumber("vertex index")));
+    }
+
+    #[test]
+    fn binary_data_is_a_read_error() {
+        // Binary data is not text, which is an error rather than a panic
+        assert!(matches!(read("binary", &[0x76, 0x20, 0xff, 0xfe, 0x00, 0x9f], ParseMode::Lenient), Err(ObjError::Io { .. })));
+    }
+
+    #[test]
+    fn unsupported_statements_depend_on_the_mode() {
+        let contents = format!("{}  curv 0 1 1 2\n", TRIANGLE);
+        let result = read("strict", contents.as_bytes(), ParseMode::Strict);
+        assert_eq!(parse_error(result), (4, 3, ObjErrorKind::UnsupportedStatement));
+        let obj = read("lenient", contents.as_bytes(), ParseMode::Lenient).unwrap();
+        assert_eq!(obj.vertices.len(), 3);
+    }
+}
@@ -0,0 +1,335 @@
+/// The encodings of the data following the header of a PLY file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// The numeric types a PLY property can be stored as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlyType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(PlyType::Int8),
+            "uchar" | "uint8" => Some(PlyType::UInt8),
+            "short" | "int16" => Some(PlyType::Int16),
+            "ushort" | "uint16" => Some(PlyType::UInt16),
+            "int" | "int32" => Some(PlyType::Int32),
+            "uint" | "uint32" => Some(PlyType::UInt32),
+            "float" | "float32" => Some(PlyType::Float32),
+            "double" | "float64" => Some(PlyType::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            PlyType::Int8 | PlyType::UInt8 => 1,
+            PlyType::Int16 | PlyType::UInt16 => 2,
+            PlyType::Int32 | PlyType::UInt32 | PlyType::Float32 => 4,
+            PlyType::Float64 => 8,
+        }
+    }
+
+    /// The largest value of an integer type, which stands for full intensity
+    /// when the type is used for a colour. Floating point colours run up to 1.
+    pub fn max_intensity(&self) -> f64 {
+        match self {
+            PlyType::Int8 => i8::MAX as f64,
+            PlyType::UInt8 => u8::MAX as f64,
+            PlyType::Int16 => i16::MAX as f64,
+            PlyType::UInt16 => u16::MAX as f64,
+            PlyType::Int32 => i32::MAX as f64,
+            PlyType::UInt32 => u32::MAX as f64,
+            PlyType::Float32 | PlyType::Float64 => 1.0,
+        }
+    }
+
+    // Decode a single value, `bytes` holds exactly `size()` bytes
+    fn decode(&self, bytes: &[u8], format: PlyFormat) -> f64 {
+        macro_rules! decode {
+            ($type:ty) => {{
+                let bytes = bytes.try_into().unwrap();
+                (if format == PlyFormat::BinaryBigEndian { <$type>::from_be_bytes(bytes) } else { <$type>::from_le_bytes(bytes) }) as f64
+            }};
+        }
+        match self {
+            PlyType::Int8 => decode!(i8),
+            PlyType::UInt8 => decode!(u8),
+            PlyType::Int16 => decode!(i16),
+            PlyType::UInt16 => decode!(u16),
+            PlyType::Int32 => decode!(i32),
+            PlyType::UInt32 => decode!(u32),
+            PlyType::Float32 => decode!(f32),
+            PlyType::Float64 => decode!(f64),
+        }
+    }
+}
+
+/// The values of one property for every item of an element. All numbers are
+/// widened to `f64`, which holds every PLY type exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlyValues {
+    Scalar(Vec<f64>),
+    List(Vec<Vec<f64>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlyProperty {
+    pub name: String,
+    pub data_type: PlyType, // The type of the values, or of the items of a list
+    count_type: Option<PlyType>, // The type of the length of a list
+    pub values: PlyValues,
+}
+
+/// A kind of item in a PLY file, e.g. `vertex` or `face`, with its properties.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlyElement {
+    pub name: String,
+    pub count: usize,
+    pub properties: Vec<PlyProperty>,
+}
+
+impl PlyElement {
+    pub fn property(&self, name: &str) -> Option<&PlyProperty> {
+        self.properties.iter().find(|property| property.name == name)
+    }
+
+    /// The values of the first of `names` that is a scalar property.
+    pub fn scalar(&self, names: &[&str]) -> Option<(&[f64], PlyType)> {
+        names.iter().find_map(|&name| match self.property(name) {
+            Some(PlyProperty { values: PlyValues::Scalar(values), data_type, .. }) => Some((&values[..], *data_type)),
+            _ => None,
+        })
+    }
+
+    /// The values of the first of `names` that is a list property.
+    pub fn list(&self, names: &[&str]) -> Option<&[Vec<f64>]> {
+        names.iter().find_map(|&name| match self.property(name) {
+            Some(PlyProperty { values: PlyValues::List(values), .. }) => Some(&values[..]),
+            _ => None,
+        })
+    }
+}
+
+/// A PLY (Stanford polygon) file, holding any elements and properties.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ply {
+    pub format: PlyFormat,
+    pub comments: Vec<String>,
+    pub elements: Vec<PlyElement>,
+}
+
+impl Ply {
+    pub fn element(&self, name: &str) -> Option<&PlyElement> {
+        self.elements.iter().find(|element| element.name == name)
+    }
+
+    /// Read an ASCII, binary little-endian or binary big-endian PLY file.
+    pub fn read_from_file(file_path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(file_path).map_err(|e| format!("{}: {}", file_path, e))?;
+        Self::parse(&bytes, file_path)
+    }
+
+    fn parse(bytes: &[u8], file_path: &str) -> Result<Self, String> {
+        let in_file = |message: &str| format!("{}: {}", file_path, message);
+
+        // The header is text up to and including the end_header line
+        let header_end = bytes.windows(10).position(|window| window == b"end_header")
+            .ok_or_else(|| in_file("Missing end_header"))?;
+        let data_start = bytes[header_end..].iter().position(|&byte| byte == b'\n')
+            .map_or(bytes.len(), |offset| header_end + offset + 1);
+        let header = std::str::from_utf8(&bytes[..header_end]).map_err(|_| in_file("The header is not valid text"))?;
+
+        let mut lines = header.lines().enumerate().map(|(index, line)| (index + 1, line.split_whitespace().collect::<Vec<_>>()));
+        match lines.next() {
+            Some((_, words)) if words == ["ply"] => {},
+            _ => return Err(in_file("Not a PLY file")),
+        }
+
+        let mut format = None;
+        let mut comments = Vec::new();
+        let mut elements: Vec<PlyElement> = Vec::new();
+        for (line, words) in lines {
+            let context = |message: &str| format!("{}:{}: {}", file_path, line, message);
+            let data_type = |name: &str| PlyType::parse(name).ok_or_else(|| context(&format!("Unknown type '{}'", name)));
+            match words.as_slice() {
+                [] => {},
+                ["format", name, _version] => {
+                    format = Some(match *name {
+                        "ascii" => PlyFormat::Ascii,
+                        "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                        "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                        _ => return Err(context(&format!("Unknown format '{}'", name))),
+                    });
+                },
+                ["comment" | "obj_info", ..] => comments.push(words[1..].join(" ")),
+                ["element", name, count] => elements.push(PlyElement {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| context(&format!("Invalid element count '{}'", count)))?,
+                    properties: Vec::new(),
+                }),
+                ["property", "list", count_type, item_type, name] => {
+                    let element = elements.last_mut().ok_or_else(|| context("Property before any element"))?;
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        data_type: data_type(item_type)?,
+                        count_type: Some(data_type(count_type)?),
+                        values: PlyValues::List(Vec::new()),
+                    });
+                },
+                ["property", value_type, name] => {
+                    let element = elements.last_mut().ok_or_else(|| context("Property before any element"))?;
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        data_type: data_type(value_type)?,
+                        count_type: None,
+                        values: PlyValues::Scalar(Vec::new()),
+                    });
+                },
+                _ => return Err(context(&format!("Invalid header line '{}'", words.join(" ")))),
+            }
+        }
+        let format = format.ok_or_else(|| in_file("Missing format"))?;
+
+        let data = &bytes[data_start.min(bytes.len())..];
+        let mut reader: Box<dyn ValueReader> = match format {
+            PlyFormat::Ascii => Box::new(AsciiReader {
+                tokens: std::str::from_utf8(data).map_err(|_| in_file("The data is not valid text"))?.split_whitespace(),
+            }),
+            _ => Box::new(BinaryReader { data, position: 0, format }),
+        };
+        // The vectors grow as values are read, as the counts in the header may be far too large
+        for element in elements.iter_mut().filter(|element| !element.properties.is_empty()) {
+            for item in 0..element.count {
+                for property in element.properties.iter_mut() {
+                    let context = |e: String| in_file(&format!("Element {} {}, property {}: {}", element.name, item, property.name, e));
+                    match &mut property.values {
+                        PlyValues::Scalar(values) => values.push(reader.read(property.data_type).map_err(context)?),
+                        PlyValues::List(lists) => {
+                            let count = reader.read(property.count_type.unwrap()).map_err(context)?;
+                            if count < 0.0 {
+                                return Err(context(format!("Negative list length {}", count)));
+                            }
+                            let list = (0..count as usize)
+                                .map(|_| reader.read(property.data_type))
+                                .collect::<Result<Vec<_>, _>>()
+                                .map_err(context)?;
+                            lists.push(list);
+                        },
+                    }
+                }
+            }
+        }
+
+        Ok(Ply { format, comments, elements })
+    }
+}
+
+// Reads the values of the body one after another
+trait ValueReader {
+    fn read(&mut self, data_type: PlyType) -> Result<f64, String>;
+}
+
+struct AsciiReader<'a> {
+    tokens: std::str::SplitWhitespace<'a>,
+}
+
+impl ValueReader for AsciiReader<'_> {
+    fn read(&mut self, _: PlyType) -> Result<f64, String> {
+        let token = self.tokens.next().ok_or("Unexpected end of file")?;
+        token.parse().map_err(|_| format!("Invalid number '{}'", token))
+    }
+}
+
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    format: PlyFormat,
+}
+
+impl ValueReader for BinaryReader<'_> {
+    fn read(&mut self, data_type: PlyType) -> Result<f64, String> {
+        let end = self.position + data_type.size();
+        let bytes = self.data.get(self.position..end).ok_or("Unexpected end of file")?;
+        self.position = end;
+        Ok(data_type.decode(bytes, self.format))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn parse(bytes: &[u8]) -> Result<Ply, String> {
+        Ply::parse(bytes, "model.ply")
+    }
+
+    #[test]
+    fn reads_ascii_elements() {
+        let ply = parse(format!("{}0\n1\n2 0 1\n", HEADER).as_bytes()).unwrap();
+        assert_eq!(ply.element("vertex").unwrap().scalar(&["x"]).unwrap().0, [0.0, 1.0]);
+        assert_eq!(ply.element("face").unwrap().list(&["vertex_indices"]).unwrap(), [vec![0.0, 1.0]]);
+    }
+
+    #[test]
+    fn header_errors_carry_the_line() {
+        let error = |text: &str| parse(text.as_bytes()).unwrap_err();
+        assert_eq!(error("ply\nformat ascii 1.0\nelement vertex many\nend_header\n"), "model.ply:3: Invalid element count 'many'");
+        assert_eq!(error("ply\nformat ascii 1.0\nproperty float x\nend_header\n"), "model.ply:3: Property before any element");
+        assert_eq!(error("ply\nformat zip 1.0\nend_header\n"), "model.ply:2: Unknown format 'zip'");
+        assert_eq!(error("ply\nformat ascii 1.0\nelement vertex 1\nproperty real x\nend_header\n"), "model.ply:4: Unknown type 'real'");
+        assert_eq!(error("ply\nformat ascii 1.0\nelement vertex\nend_header\n"), "model.ply:3: Invalid header line 'element vertex'");
+    }
+
+    #[test]
+    fn header_needs_a_format_and_an_end() {
+        assert_eq!(parse(b"").unwrap_err(), "model.ply: Missing end_header");
+        assert_eq!(parse(b"ply\nformat ascii 1.0\nelement vertex 2\n").unwrap_err(), "model.ply: Missing end_header");
+        assert_eq!(parse(b"ply\nelement vertex 0\nend_header\n").unwrap_err(), "model.ply: Missing format");
+        assert_eq!(parse(b"solid cube\nend_header\n").unwrap_err(), "model.ply: Not a PLY file");
+        assert_eq!(parse(b"ply\n\xff\nend_header\n").unwrap_err(), "model.ply: The header is not valid text");
+    }
+
+    #[test]
+    fn body_ending_early_names_the_element_and_property() {
+        assert_eq!(parse(format!("{}0\n1\n2 0", HEADER).as_bytes()).unwrap_err(),
+            "model.ply: Element face 0, property vertex_indices: Unexpected end of file");
+
+        let mut binary = b"ply\nformat binary_little_endian 1.0\nelement vertex 2\nproperty float x\nend_header\n".to_vec();
+        binary.extend_from_slice(&1.0f32.to_le_bytes());
+        binary.extend_from_slice(&[0, 0]);
+        assert_eq!(parse(&binary).unwrap_err(), "model.ply: Element vertex 1, property x: Unexpected end of file");
+    }
+
+    #[test]
+    fn invalid_values_name_the_element_and_property() {
+        assert_eq!(parse(format!("{}0\nx\n", HEADER).as_bytes()).unwrap_err(), "model.ply: Element vertex 1, property x: Invalid number 'x'");
+        assert_eq!(parse(format!("{}0\n1\n-1\n", HEADER).as_bytes()).unwrap_err(),
+            "model.ply: Element face 0, property vertex_indices: Negative list length -1");
+    }
+
+    #[test]
+    fn huge_counts_fail_without_allocating() {
+        let ply = "ply\nformat binary_little_endian 1.0\nelement vertex 4000000000\nproperty double x\nend_header\n";
+        assert_eq!(parse(ply.as_bytes()).unwrap_err(), "model.ply: Element vertex 0, property x: Unexpected end of file");
+
+        let mut list = b"ply\nformat binary_little_endian 1.0\nelement face 1\nproperty list uint int vertex_indices\nend_header\n".to_vec();
+        list.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse(&list).unwrap_err(), "model.ply: Element face 0, property vertex_indices: Unexpected end of file");
+    }
+}
@@ -0,0 +1,414 @@
+/// Decode a PNG image.
+///
+/// All bit depths, colour types and interlacing are supported. Returns the
+/// width, height and the pixels as RGBA8, row by row from the top.
+pub fn decode_png(data: &[u8]) -> Result<(usize, usize, Vec<[u8; 4]>), String> {
+    const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    if !data.starts_with(&SIGNATURE) {
+        return Err("Not a PNG file".to_string());
+    }
+
+    let mut header = None;
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    let mut compressed = Vec::new();
+    let mut position = SIGNATURE.len();
+    while position + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[position..position + 4].try_into().unwrap()) as usize;
+        let kind = &data[position + 4..position + 8];
+        let contents = data.get(position + 8..).and_then(|rest| rest.get(..length)).ok_or("Truncated chunk")?;
+        position += 12 + length; // Length, type, contents and CRC
+        match kind {
+            b"IHDR" => header = Some(Header::parse(contents)?),
+            b"PLTE" => palette = contents.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+            b"tRNS" => {
+                // Only the alpha of palette entries is used, colour keys are ignored
+                for (entry, &alpha) in palette.iter_mut().zip(contents) {
+                    entry[3] = alpha;
+                }
+            },
+            b"IDAT" => compressed.extend_from_slice(contents),
+            b"IEND" => break,
+            _ => {},
+        }
+    }
+    let header = header.ok_or("Missing IHDR chunk")?;
+    let passes: &[(usize, usize, usize, usize)] = if header.interlaced {
+        // Adam7 passes as (start x, start y, step x, step y)
+        &[(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)]
+    } else {
+        &[(0, 0, 1, 1)]
+    };
+    // The size of each pass as (start x, start y, step x, step y, width, height,
+    // row size), with the size of the filtered data of all passes
+    let mut layout = Vec::new();
+    let mut expected_size: usize = 0;
+    for &(start_x, start_y, step_x, step_y) in passes {
+        let width = header.width.saturating_sub(start_x).div_ceil(step_x);
+        let height = header.height.saturating_sub(start_y).div_ceil(step_y);
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let row_size = width.checked_mul(header.bits_per_pixel()).ok_or("The image is too large")?.div_ceil(8);
+        expected_size = (row_size + 1).checked_mul(height).and_then(|size| size.checked_add(expected_size)).ok_or("The image is too large")?;
+        layout.push((start_x, start_y, step_x, step_y, width, height, row_size));
+    }
+
+    // Only allocate the image once the data is known to fill it
+    let data = zlib_decompress(&compressed)?;
+    if data.len() < expected_size {
+        return Err("Image data is truncated".to_string());
+    }
+    let mut pixels = vec![[0, 0, 0, 255]; header.width * header.height];
+    let mut offset = 0;
+    for (start_x, start_y, step_x, step_y, width, height, row_size) in layout {
+        let size = (row_size + 1) * height;
+        let pass = &data[offset..offset + size];
+        offset += size;
+
+        let rows = unfilter(pass, row_size, header.bytes_per_pixel())?;
+        for (y, row) in rows.chunks_exact(row_size).enumerate() {
+            for x in 0..width {
+                let pixel = header.pixel(row, x, &palette)?;
+                pixels[(start_y + y * step_y) * header.width + start_x + x * step_x] = pixel;
+            }
+        }
+    }
+
+    Ok((header.width, header.height, pixels))
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: usize,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn parse(contents: &[u8]) -> Result<Self, String> {
+        if contents.len() < 13 {
+            return Err("Invalid IHDR chunk".to_string());
+        }
+        let header = Header {
+            width: u32::from_be_bytes(contents[0..4].try_into().unwrap()) as usize,
+            height: u32::from_be_bytes(contents[4..8].try_into().unwrap()) as usize,
+            bit_depth: contents[8] as usize,
+            color_type: contents[9],
+            interlaced: contents[12] == 1,
+        };
+        let valid_depths: &[usize] = match header.color_type {
+            0 => &[1, 2, 4, 8, 16],
+            3 => &[1, 2, 4, 8],
+            2 | 4 | 6 => &[8, 16],
+            _ => return Err(format!("Unknown colour type {}", header.color_type)),
+        };
+        if !valid_depths.contains(&header.bit_depth) || header.width == 0 || header.height == 0 {
+            return Err("Invalid image format".to_string());
+        }
+        Ok(header)
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth
+    }
+
+    // Distance to the corresponding byte of the previous pixel used by the filters
+    fn bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    fn pixel(&self, row: &[u8], x: usize, palette: &[[u8; 4]]) -> Result<[u8; 4], String> {
+        // Samples are scaled to 8 bits, 16 bit samples keep their high byte
+        let sample = |channel: usize| -> u8 {
+            let index = x * self.channels() + channel;
+            match self.bit_depth {
+                16 => row[index * 2],
+                8 => row[index],
+                depth => {
+                    let bit = index * depth;
+                    let value = (row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1);
+                    if self.color_type == 3 { value } else { value * (255 / ((1 << depth) - 1)) }
+                },
+            }
+        };
+        Ok(match self.color_type {
+            0 => [sample(0), sample(0), sample(0), 255],
+            2 => [sample(0), sample(1), sample(2), 255],
+            3 => *palette.get(sample(0) as usize).ok_or("Palette index out of range")?,
+            4 => [sample(0), sample(0), sample(0), sample(1)],
+            _ => [sample(0), sample(1), sample(2), sample(3)],
+        })
+    }
+}
+
+// Undo the filter of every row, where each row starts with its filter type
+fn unfilter(data: &[u8], row_size: usize, bytes_per_pixel: usize) -> Result<Vec<u8>, String> {
+    let mut rows = vec![0u8; data.len() / (row_size + 1) * row_size];
+    for (y, filtered) in data.chunks_exact(row_size + 1).enumerate() {
+        let (previous, current) = rows.split_at_mut(y * row_size);
+        let above = if y == 0 { None } else { Some(&previous[(y - 1) * row_size..]) };
+        let current = &mut current[..row_size];
+        for i in 0..row_size {
+            let left = if i >= bytes_per_pixel { current[i - bytes_per_pixel] } else { 0 };
+            let up = above.map_or(0, |above| above[i]);
+            let up_left = if i >= bytes_per_pixel { above.map_or(0, |above| above[i - bytes_per_pixel]) } else { 0 };
+            let predictor = match filtered[0] {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                filter => return Err(format!("Unknown filter type {}", filter)),
+            };
+            current[i] = filtered[i + 1].wrapping_add(predictor);
+        }
+    }
+    Ok(rows)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+/// Decompress a zlib stream. The checksum is not verified.
+fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 2 || data[0] & 0x0f != 8 || !(data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31) {
+        return Err("Invalid zlib header".to_string());
+    }
+    inflate(&data[2..])
+}
+
+// Reads the bits of a deflate stream, least significant bit first
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize, // In bits
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: usize) -> Result<usize, String> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.position / 8).ok_or("Compressed data is truncated")?;
+            value |= (((byte >> (self.position % 8)) & 1) as usize) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// A canonical Huffman code, described by the number of codes of each length
+/// and the symbols ordered by their code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|&symbol| lengths[symbol as usize] != 0).collect();
+        symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<usize, String> {
+        // Codes of each length follow directly on the ones of the previous length
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for length in 1..16 {
+            code |= reader.bits(1)?;
+            let count = self.counts[length] as usize;
+            if code < first + count {
+                return Ok(self.symbols[index + code - first] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid Huffman code".to_string())
+    }
+}
+
+const LENGTH_BASES: [usize; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA_BITS: [usize; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [usize; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA_BITS: [usize; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Decompress a raw deflate stream.
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader { data, position: 0 };
+    let mut output = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                // Stored block, aligned to the next byte
+                let start = reader.position.div_ceil(8);
+                let header = data.get(start..start + 4).ok_or("Compressed data is truncated")?;
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                output.extend_from_slice(data.get(start + 4..start + 4 + length).ok_or("Compressed data is truncated")?);
+                reader.position = (start + 4 + length) * 8;
+            },
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                inflate_block(&mut reader, &mut output, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            },
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            },
+            _ => return Err("Invalid block type".to_string()),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    const ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+    let literal_count = reader.bits(5)? + 257;
+    let distance_count = reader.bits(5)? + 1;
+    let code_length_count = reader.bits(4)? + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in ORDER.iter().take(code_length_count) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or("Repeat without a previous length")?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("Too many code lengths".to_string());
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASES.len() {
+                    return Err("Invalid length code".to_string());
+                }
+                let length = LENGTH_BASES[index] + reader.bits(LENGTH_EXTRA_BITS[index])?;
+                let index = distances.decode(reader)?;
+                if index >= DISTANCE_BASES.len() {
+                    return Err("Invalid distance code".to_string());
+                }
+                let distance = DISTANCE_BASES[index] + reader.bits(DISTANCE_EXTRA_BITS[index])?;
+                if distance > output.len() {
+                    return Err("Distance reaches before the start of the data".to_string());
+                }
+                // The copy may overlap the bytes it produces
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A PNG of the given size and colour type whose image data is stored
+    // uncompressed. The CRCs are left at zero, as they are not checked.
+    fn png(width: u32, height: u32, color_type: u8, filtered: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![137, 80, 78, 71, 13, 10, 26, 10];
+        let mut chunk = |kind: &[u8], contents: &[u8]| {
+            bytes.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(kind);
+            bytes.extend_from_slice(contents);
+            bytes.extend_from_slice(&[0; 4]);
+        };
+        let mut header = [width.to_be_bytes(), height.to_be_bytes()].concat();
+        header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+        chunk(b"IHDR", &header);
+        let mut zlib = vec![0x78, 0x01, 1];
+        zlib.extend_from_slice(&(filtered.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(filtered.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(filtered);
+        chunk(b"IDAT", &zlib);
+        chunk(b"IEND", &[]);
+        bytes
+    }
+
+    #[test]
+    fn decodes_stored_image() {
+        let (width, height, pixels) = decode_png(&png(2, 1, 2, &[0, 255, 0, 0, 0, 0, 255])).unwrap();
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(pixels, [[255, 0, 0, 255], [0, 0, 255, 255]]);
+    }
+
+    #[test]
+    fn huge_image_with_little_data_fails_before_allocating() {
+        assert_eq!(decode_png(&png(2, 1, 2, &[0, 255, 0, 0])).unwrap_err(), "Image data is truncated");
+        assert_eq!(decode_png(&png(100_000, 100_000, 6, &[0; 16])).unwrap_err(), "Image data is truncated");
+        assert_eq!(decode_png(&png(u32::MAX, u32::MAX, 6, &[0; 16])).unwrap_err(), "The image is too large");
+    }
+
+    #[test]
+    fn missing_chunks_and_data_are_errors() {
+        let image = png(2, 1, 2, &[0, 255, 0, 0, 0, 0, 255]);
+        assert_eq!(decode_png(&image[..20]).unwrap_err(), "Truncated chunk");
+        assert_eq!(decode_png(&image[..8]).unwrap_err(), "Missing IHDR chunk");
+
+        // An IDAT chunk that ends inside the header of the stored block
+        let mut cut = image[..47].to_vec();
+        cut[33..37].copy_from_slice(&6u32.to_be_bytes());
+        cut.extend_from_slice(&image[image.len() - 16..]);
+        assert_eq!(decode_png(&cut).unwrap_err(), "Compressed data is truncated");
+    }
+
+    #[test]
+    fn invalid_headers_are_errors() {
+        assert_eq!(decode_png(b"").unwrap_err(), "Not a PNG file");
+        assert_eq!(decode_png(b"GIF89a\0\0\0\0").unwrap_err(), "Not a PNG file");
+        assert_eq!(decode_png(&png(2, 1, 5, &[])).unwrap_err(), "Unknown colour type 5");
+        assert_eq!(decode_png(&png(0, 1, 2, &[])).unwrap_err(), "Invalid image format");
+    }
+
+    #[test]
+    fn invalid_image_data_is_an_error() {
+        assert_eq!(decode_png(&png(2, 1, 2, &[7, 255, 0, 0, 0, 0, 255])).unwrap_err(), "Unknown filter type 7");
+
+        let mut bad_zlib = png(2, 1, 2, &[0, 255, 0, 0, 0, 0, 255]);
+        bad_zlib[41] = 0x79;
+        assert_eq!(decode_png(&bad_zlib).unwrap_err(), "Invalid zlib header");
+    }
+}
@@ -0,0 +1,365 @@
+use std::sync::Mutex;
+
+use crate::{float3::Float3, interpolate::{perspective_correct, Interpolate}, render::{FrontFace, RenderState, RenderTarget}, shader::FragmentInput};
+
+/// Width and height in pixels of the square tiles the render target is split into.
+const TILE_SIZE: usize = 32;
+
+/// Vertex positions are snapped to a grid of 1 / 2^SUBPIXEL_BITS of a pixel.
+const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL_ONE: i64 = 1 << SUBPIXEL_BITS;
+
+/// An edge function `E(p) = (p.x - from.x) * dy - (p.y - from.y) * dx` in
+/// fixed point, evaluated at pixel centres.
+///
+/// It is positive for points on the inside of a clockwise triangle, zero on the
+/// edge itself and changes by a constant amount per pixel, so it can be stepped
+/// incrementally across a row instead of being recomputed.
+#[derive(Debug, Clone, Copy)]
+struct EdgeFunction {
+    origin: i64, // Value at the centre of pixel (0, 0)
+    step_x: i64,
+    step_y: i64,
+    // Pixels exactly on the edge are only drawn for top and left edges, so
+    // triangles sharing an edge never both draw the same pixel
+    bias: i64,
+}
+
+impl EdgeFunction {
+    fn new(from: (i64, i64), to: (i64, i64)) -> Self {
+        let dx = to.0 - from.0;
+        let dy = to.1 - from.1;
+        let center = SUBPIXEL_ONE / 2;
+        // Rows grow downwards, so clockwise triangles run down their left
+        // edges and right to left along their top edges
+        let is_top_left = dy > 0 || (dy == 0 && dx < 0);
+        EdgeFunction {
+            origin: (center - from.0) * dy - (center - from.1) * dx,
+            step_x: dy * SUBPIXEL_ONE,
+            step_y: -dx * SUBPIXEL_ONE,
+            bias: if is_top_left { 0 } else { -1 },
+        }
+    }
+
+    fn at(&self, x: usize, y: usize) -> i64 {
+        self.origin + x as i64 * self.step_x + y as i64 * self.step_y
+    }
+}
+
+/// A screen-space triangle that survived culling, ready to be rasterized.
+pub struct ScreenTriangle<V> {
+    // Edge functions opposite vertex a, b and c, i.e. their barycentric weights
+    edges: [EdgeFunction; 3],
+    inverse_area: f32,
+    inverse_depths: Float3,
+    attributes: [V; 3],
+    material: usize,
+    // Inclusive pixel bounds, clamped to the render target
+    min: (usize, usize),
+    max: (usize, usize),
+}
+
+impl<V: Interpolate> ScreenTriangle<V> {
+    /// Set up a triangle from vertices given as (pixel x, pixel y, view depth).
+    ///
+    /// Returns `None` if the triangle is degenerate after snapping to the
+    /// sub-pixel grid, or culled according to `render_state`.
+    pub fn new(mut vertices: [Float3; 3], mut attributes: [V; 3], material: usize, render_state: &RenderState, width: usize, height: usize) -> Option<Self> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let snap = |v: &Float3| (
+            (v.x * SUBPIXEL_ONE as f32).round() as i64,
+            (v.y * SUBPIXEL_ONE as f32).round() as i64,
+        );
+        let mut fixed = [snap(&vertices[0]), snap(&vertices[1]), snap(&vertices[2])];
+
+        // Twice the signed area, positive when the triangle is wound clockwise on screen
+        let [a, b, c] = fixed;
+        let area = (c.0 - a.0) * (b.1 - a.1) - (c.1 - a.1) * (b.0 - a.0);
+        if area == 0 {
+            return None; // Degenerate triangle, nothing to draw
+        }
+
+        let front_facing = match render_state.front_face {
+            FrontFace::Clockwise => area > 0,
+            FrontFace::CounterClockwise => area < 0,
+        };
+        if render_state.cull_mode.culls(front_facing) {
+            return None;
+        }
+
+        // The edge functions assume a clockwise triangle, so flip the others around
+        if area < 0 {
+            vertices.swap(1, 2);
+            attributes.swap(1, 2);
+            fixed.swap(1, 2);
+        }
+
+        let [a, b, c] = fixed;
+        let min_x = a.0.min(b.0).min(c.0) >> SUBPIXEL_BITS;
+        let max_x = a.0.max(b.0).max(c.0) >> SUBPIXEL_BITS;
+        let min_y = a.1.min(b.1).min(c.1) >> SUBPIXEL_BITS;
+        let max_y = a.1.max(b.1).max(c.1) >> SUBPIXEL_BITS;
+        let clamp = |value: i64, size: usize| value.clamp(0, size as i64 - 1) as usize;
+
+        Some(ScreenTriangle {
+            edges: [EdgeFunction::new(b, c), EdgeFunction::new(c, a), EdgeFunction::new(a, b)],
+            inverse_area: 1.0 / area.abs() as f32,
+            inverse_depths: Float3::new(1.0 / vertices[0].z, 1.0 / vertices[1].z, 1.0 / vertices[2].z),
+            attributes,
+            material,
+            min: (clamp(min_x, width), clamp(min_y, height)),
+            max: (clamp(max_x, width), clamp(max_y, height)),
+        })
+    }
+}
+
+/// A rectangular part of the render target that can be rasterized
+/// independently of all other tiles.
+struct Tile<'a> {
+    // Top-left pixel of the tile in the render target
+    x: usize,
+    y: usize,
+    color_rows: Vec<&'a mut [[u8; 4]]>,
+    depth_rows: Vec<&'a mut [f32]>,
+}
+
+/// The triangles of every model drawn into a frame, rasterized together so
+/// the worker threads are started once per frame rather than once per model.
+#[derive(Default)]
+pub struct Frame<'a> {
+    draws: Vec<Box<dyn Draw + 'a>>,
+}
+
+impl<'a> Frame<'a> {
+    /// Add triangles to be drawn after those already in the frame, shaded by
+    /// `shade`.
+    ///
+    /// The per-vertex attributes are interpolated with perspective correction
+    /// and handed to `shade` for every covered pixel that passes the depth
+    /// test. If it returns `None` the pixel is discarded and neither colour
+    /// nor depth is written.
+    pub fn add<V, F>(&mut self, triangles: Vec<ScreenTriangle<V>>, shade: F)
+    where
+        V: Interpolate + Sync + 'a,
+        F: Fn(&FragmentInput<V>) -> Option<Float3> + Sync + 'a,
+    {
+        if !triangles.is_empty() {
+            self.draws.push(Box::new(DrawCall { triangles, shade }));
+        }
+    }
+
+    /// Rasterize all triangles of the frame into the target.
+    ///
+    /// The target is split into tiles and each triangle is binned into the
+    /// tiles its bounding box overlaps. Tiles are then rasterized in parallel,
+    /// but every tile draws its triangles in the order they were added, so the
+    /// output is identical to rasterizing on a single thread.
+    pub fn rasterize(self, target: &mut RenderTarget) {
+        let threads = target.render_state.thread_count();
+        let width = target.width();
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = target.height().div_ceil(TILE_SIZE);
+
+        // Bins hold the index of the draw call and of the triangle within it
+        let mut bins: Vec<Vec<(usize, usize)>> = vec![Vec::new(); tiles_x * tiles_y];
+        for (draw_index, draw) in self.draws.iter().enumerate() {
+            for index in 0..draw.triangle_count() {
+                let (min, max) = draw.bounds(index);
+                for tile_y in min.1 / TILE_SIZE..=max.1 / TILE_SIZE {
+                    for tile_x in min.0 / TILE_SIZE..=max.0 / TILE_SIZE {
+                        bins[tile_y * tiles_x + tile_x].push((draw_index, index));
+                    }
+                }
+            }
+        }
+
+        // Hand out disjoint slices of every row to the tiles they belong to
+        let mut tiles: Vec<Tile> = (0..tiles_x * tiles_y)
+            .map(|i| Tile {
+                x: (i % tiles_x) * TILE_SIZE,
+                y: (i / tiles_x) * TILE_SIZE,
+                color_rows: Vec::with_capacity(TILE_SIZE),
+                depth_rows: Vec::with_capacity(TILE_SIZE),
+            })
+            .collect();
+        let (pixels, depth_buffer) = target.buffers_mut();
+        for (y, (color_row, depth_row)) in pixels.chunks_mut(width).zip(depth_buffer.chunks_mut(width)).enumerate() {
+            let tile_row = (y / TILE_SIZE) * tiles_x;
+            for (tile_x, (color, depth)) in color_row.chunks_mut(TILE_SIZE).zip(depth_row.chunks_mut(TILE_SIZE)).enumerate() {
+                tiles[tile_row + tile_x].color_rows.push(color);
+                tiles[tile_row + tile_x].depth_rows.push(depth);
+            }
+        }
+
+        let work: Vec<(Tile, &Vec<(usize, usize)>)> = tiles.into_iter()
+            .zip(bins.iter())
+            .filter(|(_, bin)| !bin.is_empty())
+            .collect();
+        let draw_bin = |tile: &mut Tile, bin: &[(usize, usize)]| {
+            for &(draw, index) in bin {
+                self.draws[draw].rasterize(tile, index);
+            }
+        };
+
+        if threads <= 1 || work.len() <= 1 {
+            for (mut tile, bin) in work {
+                draw_bin(&mut tile, bin);
+            }
+            return;
+        }
+
+        let queue = Mutex::new(work.into_iter());
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    let next = queue.lock().expect("Tile queue lock poisoned").next();
+                    match next {
+                        Some((mut tile, bin)) => draw_bin(&mut tile, bin),
+                        None => break,
+                    }
+                });
+            }
+        });
+    }
+}
+
+// The triangles of one draw call, behind a trait so draw calls with different
+// varyings and shaders can share a frame
+trait Draw: Sync {
+    fn triangle_count(&self) -> usize;
+
+    // Inclusive pixel bounds of a triangle
+    fn bounds(&self, index: usize) -> ((usize, usize), (usize, usize));
+
+    fn rasterize(&self, tile: &mut Tile, index: usize);
+}
+
+struct DrawCall<V, F> {
+    triangles: Vec<ScreenTriangle<V>>,
+    shade: F,
+}
+
+impl<V, F> Draw for DrawCall<V, F>
+where
+    V: Interpolate + Sync,
+    F: Fn(&FragmentInput<V>) -> Option<Float3> + Sync,
+{
+    fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    fn bounds(&self, index: usize) -> ((usize, usize), (usize, usize)) {
+        (self.triangles[index].min, self.triangles[index].max)
+    }
+
+    fn rasterize(&self, tile: &mut Tile, index: usize) {
+        rasterize_triangle(tile, &self.triangles[index], &self.shade);
+    }
+}
+
+// Pixel offsets of the quad, the top-left pixel first, then its neighbour in
+// the row and the one in the next row
+const QUAD: [(usize, usize); 4] = [(0, 0), (1, 0), (0, 1), (1, 1)];
+
+fn rasterize_triangle<V, F>(tile: &mut Tile, triangle: &ScreenTriangle<V>, shade: &F)
+where
+    V: Interpolate,
+    F: Fn(&FragmentInput<V>) -> Option<Float3>,
+{
+    let tile_end_x = tile.x + tile.color_rows[0].len() - 1;
+    let tile_end_y = tile.y + tile.color_rows.len() - 1;
+
+    // Quads start on even pixels. Tiles have an even size, so a quad never
+    // crosses into another tile.
+    let block_start_x = triangle.min.0.max(tile.x) & !1;
+    let block_end_x = triangle.max.0.min(tile_end_x);
+    let block_start_y = triangle.min.1.max(tile.y) & !1;
+    let block_end_y = triangle.max.1.min(tile_end_y);
+
+    let edges = triangle.edges;
+    for y in (block_start_y..=block_end_y).step_by(2) {
+        let mut row = edges.map(|edge| edge.at(block_start_x, y));
+        for x in (block_start_x..=block_end_x).step_by(2) {
+            let values = QUAD.map(|(dx, dy)| {
+                [0, 1, 2].map(|i| row[i] + dx as i64 * edges[i].step_x + dy as i64 * edges[i].step_y)
+            });
+            for i in 0..3 {
+                row[i] += 2 * edges[i].step_x;
+            }
+
+            let covered = values.map(|w| (0..3).all(|i| w[i] + edges[i].bias >= 0));
+            if !covered.contains(&true) {
+                continue;
+            }
+
+            // Pixels of the quad outside the triangle are still interpolated,
+            // so the derivatives are defined for the covered ones
+            let [a, b, c] = triangle.attributes;
+            let interpolated = values.map(|w| {
+                let weight = Float3::new(w[0] as f32, w[1] as f32, w[2] as f32) * triangle.inverse_area;
+                let (weight, depth) = perspective_correct(weight, triangle.inverse_depths);
+                (V::weighted_sum(a, b, c, weight), depth)
+            });
+            let difference = Float3::new(1.0, -1.0, 0.0);
+            let ddx = V::weighted_sum(interpolated[1].0, interpolated[0].0, interpolated[0].0, difference);
+            let ddy = V::weighted_sum(interpolated[2].0, interpolated[0].0, interpolated[0].0, difference);
+
+            for (i, &(dx, dy)) in QUAD.iter().enumerate() {
+                let (pixel_x, pixel_y) = (x + dx, y + dy);
+                if !covered[i] || pixel_x > block_end_x || pixel_y > block_end_y {
+                    continue;
+                }
+
+                let (varyings, depth) = interpolated[i];
+                let depth_slot = &mut tile.depth_rows[pixel_y - tile.y][pixel_x - tile.x];
+                if depth > *depth_slot {
+                    continue;
+                }
+                let fragment = FragmentInput { varyings, ddx, ddy, material: triangle.material };
+                if let Some(color) = shade(&fragment) {
+                    tile.color_rows[pixel_y - tile.y][pixel_x - tile.x] = color.to_rgba8();
+                    *depth_slot = depth;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{float3::Float3, obj::{Obj, ParseMode}, render::{Model, RenderTarget}, scene::Scene, triangulate::Triangulation};
+
+    // The colour and depth buffers after drawing the scene on the given number of threads
+    fn draw(scene: &Scene, threads: usize) -> (Vec<[u8; 4]>, Vec<u32>) {
+        // Odd sizes leave partial tiles at the right and bottom edges
+        let mut target = RenderTarget::new(301, 211);
+        target.render_state.threads = threads;
+        scene.draw(&mut target, false);
+        let (pixels, depth_buffer) = target.buffers_mut();
+        (pixels.to_vec(), depth_buffer.iter().map(|depth| depth.to_bits()).collect())
+    }
+
+    #[test]
+    fn threaded_output_matches_single_thread() {
+        // Two overlapping models, so tiles draw triangles of both
+        let obj = Obj::read_from_file("assets/monke.obj", ParseMode::Lenient).expect("The bundled model loads");
+        let mut front = Model::from(obj, Triangulation::Fan);
+        front.transform.yaw = 0.6;
+        front.transform.pitch = 0.3;
+        let mut back = front.clone();
+        back.transform.yaw = -0.4;
+        back.transform.position = Float3::new(0.8, 0.3, 1.0);
+        let scene = Scene { models: vec![front, back], lights: Scene::default_lights(), camera: Scene::default_camera(3.0) };
+
+        let (single_pixels, single_depth) = draw(&scene, 1);
+        assert!(single_pixels.iter().any(|&pixel| pixel != single_pixels[0]), "The models cover part of the image");
+        for threads in [2, 3, 8] {
+            let (pixels, depth) = draw(&scene, threads);
+            assert!(pixels == single_pixels, "Colours differ with {} threads", threads);
+            assert!(depth == single_depth, "Depths differ with {} threads", threads);
+        }
+    }
+}
@@ -1,12 +1,111 @@
-use crate::{float2::Float2, float3::{Float3}, transform::{Transform}, triangle::{Triangle2D, Triangle3D}};
+use std::collections::HashMap;
 
+use crate::{camera::Camera, clip::{clip_triangle, ClipVertex}, float2::Float2, float3::{Float3}, float4::Float4, gltf::{Gltf, MeshInstance}, material::Material, obj::{FaceElement, Obj, ObjObject}, raster::{Frame, ScreenTriangle}, shader::{Shader, Uniforms}, stl::Stl, transform::{Transform}, triangle::Triangle3D, triangulate::Triangulation};
+
+/// The colour and depth buffers that triangles are rasterized into, along with
+/// the view settings used to project onto them.
+///
+/// Both buffers are stored row by row in a single contiguous allocation, with
+/// colours already packed as RGBA8 so presenting a frame is a plain copy.
 #[derive(Default)]
 pub struct RenderTarget {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 4]>,
+    depth_buffer: Vec<f32>, 
+    pub render_state: RenderState,
+}
+
+/// A read-only view of the colour buffer of a `RenderTarget`.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageView<'a> {
     pub width: usize,
     pub height: usize,
-    pub pixels: Vec<Vec<Float3>>,
-    pub fov: f32, // Field of view
-    pub depth_buffer: Vec<Vec<f32>>, 
+    pub stride: usize, // Number of pixels between the start of two rows
+    pixels: &'a [[u8; 4]],
+}
+
+impl<'a> ImageView<'a> {
+    pub fn row(&self, y: usize) -> &'a [[u8; 4]] {
+        let start = y * self.stride;
+        &self.pixels[start..start + self.width]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &'a [[u8; 4]]> + '_ {
+        (0..self.height).map(move |y| self.row(y))
+    }
+}
+
+const CLEAR_COLOR: [u8; 4] = [0, 0, 0, 255];
+
+/// Fixed-function state that controls how triangles are rasterized.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RenderState {
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub threads: usize, // Number of rasterizer threads, 0 uses all available cores
+}
+
+impl RenderState {
+    pub fn thread_count(&self) -> usize {
+        match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+}
+
+/// Which faces to discard before rasterization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CullMode {
+    /// Draw both sides, e.g. for double-sided geometry like a plane.
+    None,
+    #[default]
+    Back,
+    Front,
+}
+
+/// The winding order of front faces, as seen on screen with y pointing up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrontFace {
+    #[default]
+    Clockwise,
+    CounterClockwise,
+}
+
+impl std::str::FromStr for CullMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(CullMode::None),
+            "back" => Ok(CullMode::Back),
+            "front" => Ok(CullMode::Front),
+            _ => Err(format!("Unknown cull mode '{}', expected none, back or front", s)),
+        }
+    }
+}
+
+impl std::str::FromStr for FrontFace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cw" | "clockwise" => Ok(FrontFace::Clockwise),
+            "ccw" | "counterclockwise" => Ok(FrontFace::CounterClockwise),
+            _ => Err(format!("Unknown front face '{}', expected cw or ccw", s)),
+        }
+    }
+}
+
+impl CullMode {
+    pub fn culls(&self, front_facing: bool) -> bool {
+        match self {
+            CullMode::None => false,
+            CullMode::Back => !front_facing,
+            CullMode::Front => front_facing,
+        }
+    }
 }
 
 impl RenderTarget {
@@ -14,24 +113,32 @@ impl RenderTarget {
         RenderTarget {
             width,
             height,
-            pixels: vec![vec![Float3::zero(); width]; height],
-            fov: 60.0, // Default field of view
-            depth_buffer: vec![vec![f32::INFINITY; width]; height],
+            pixels: vec![CLEAR_COLOR; width * height],
+            depth_buffer: vec![f32::INFINITY; width * height],
+            render_state: RenderState::default(),
         }
     }
     
     pub fn clear(&mut self) {
-        for row in &mut self.pixels {
-            for pixel in row {
-                *pixel = Float3::zero();
-            }
-        }
+        self.pixels.fill(CLEAR_COLOR);
+        self.depth_buffer.fill(f32::INFINITY);
+    }
 
-        for row in &mut self.depth_buffer {
-            for depth in row {
-                *depth = f32::INFINITY;
-            }
-        }
+    /// Change the size of the buffers. The contents are cleared.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.pixels.resize(width * height, CLEAR_COLOR);
+        self.depth_buffer.resize(width * height, f32::INFINITY);
+        self.clear();
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
     }
 
     pub fn size(&self) -> Float2 {
@@ -40,19 +147,43 @@ impl RenderTarget {
             y: self.height as f32,
         }
     }
+
+    /// Mutable access to the whole colour and depth buffers.
+    pub fn buffers_mut(&mut self) -> (&mut [[u8; 4]], &mut [f32]) {
+        (&mut self.pixels, &mut self.depth_buffer)
+    }
+
+    pub fn view(&self) -> ImageView<'_> {
+        ImageView {
+            width: self.width,
+            height: self.height,
+            stride: self.width,
+            pixels: &self.pixels,
+        }
+    }
+
+    /// Copy the colour buffer into an RGBA8 frame of the same size, e.g. the
+    /// frame of a `pixels::Pixels` surface.
+    pub fn copy_to_rgba8(&self, frame: &mut [u8]) {
+        frame.copy_from_slice(self.pixels.as_flattened());
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Model {
+    pub name: String,
     pub triangles: Vec<Triangle3D>,
     pub transform: Transform,
+    pub materials: Vec<Material>, // Indexed by the triangles, the first one is the default material
 }
 
 impl Model {
     pub fn new() -> Self {
         Model {
+            name: String::new(),
             triangles: Vec::new(),
-            transform: Transform { yaw: 0.0, pitch: 0.0, position: Float3::zero() }
+            transform: Transform::default(),
+            materials: vec![Material::default()],
         }
     }
 
@@ -61,12 +192,101 @@ impl Model {
         self.triangles.len() - 1 // Return the index of the new triangle
     }
 
-    pub fn from(obj: crate::obj::Obj) -> Self {
+    /// Convert a whole OBJ file into a single model.
+    pub fn from(mut obj: Obj, triangulation: Triangulation) -> Self {
+        obj.generate_normals();
+        Self::from_faces(&obj, &obj.faces, triangulation)
+    }
+
+    /// Convert one object or group of an OBJ file into a model of its own,
+    /// with only the materials it uses. Expects the normals of the OBJ to be
+    /// generated already.
+    pub fn from_object(obj: &Obj, object: &ObjObject, triangulation: Triangulation) -> Self {
+        let mut model = Self::from_faces(obj, &obj.faces[object.faces.clone()], triangulation);
+        model.name = object.full_name();
+        model
+    }
+
+    /// Convert an STL file into a model with flat shaded facets.
+    pub fn from_stl(stl: &Stl) -> Self {
+        let mut model = Model::new();
+        model.name = stl.name.clone();
+        for facet in stl.facets.iter() {
+            let [a, b, c] = facet.vertices;
+            let mut triangle = Triangle3D::new(a, b, c);
+            triangle.normals = [facet.normal; 3];
+            triangle.set_color(model.materials[0].diffuse);
+            model.add_triangle(triangle);
+        }
+        model
+    }
+
+    /// Convert a mesh placed in a glTF scene into a model. The vertices stay in
+    /// the space of the mesh and the transform of the node becomes the
+    /// placement of the model.
+    pub fn from_gltf(gltf: &Gltf, instance: &MeshInstance) -> Self {
         let mut model = Model::new();
-        for face in obj.faces.iter() {
-            let mut triangles = Triangle3D::create_triangles_from_face(&obj, face);
+        model.transform.placement = instance.world;
+        // Mirroring reverses the winding of the triangles when they are drawn, so reverse it here too
+        let mirrored = instance.world.determinant() < 0.0;
+        // Primitives without a material use the default one in front of the glTF materials
+        let mut materials: HashMap<Option<usize>, usize> = HashMap::from([(None, 0)]);
+        for primitive in gltf.meshes[instance.mesh].primitives.iter() {
+            let material = *materials.entry(primitive.material.filter(|&i| i < gltf.materials.len())).or_insert_with(|| {
+                model.materials.push(gltf.materials[primitive.material.unwrap()].clone());
+                model.materials.len() - 1
+            });
+            for corners in primitive.triangles.iter() {
+                let corners = if mirrored { [corners[0], corners[2], corners[1]] } else { *corners };
+                let [a, b, c] = corners.map(|i| primitive.positions[i]);
+                let mut triangle = Triangle3D::new(a, b, c);
+                triangle.material = material;
+                if let Some(normals) = &primitive.normals {
+                    triangle.normals = corners.map(|i| normals[i]);
+                }
+                if let Some(uvs) = &primitive.uvs {
+                    triangle.uvs = corners.map(|i| uvs[i]);
+                }
+                // Vertex colours are multiplied with the base colour of the material
+                let diffuse = model.materials[material].diffuse;
+                triangle.colors = match &primitive.colors {
+                    Some(colors) => corners.map(|i| colors[i] * diffuse),
+                    None => [diffuse; 3],
+                };
+                model.add_triangle(triangle);
+            }
+        }
+        model
+    }
+
+    /// The triangles moved into world space by the transform of the model.
+    pub fn world_triangles(&self) -> impl Iterator<Item = Triangle3D> + '_ {
+        self.triangles.iter().map(|triangle| Triangle3D {
+            a: self.transform.to_world_point(&triangle.a),
+            b: self.transform.to_world_point(&triangle.b),
+            c: self.transform.to_world_point(&triangle.c),
+            normals: triangle.normals.map(|normal| self.transform.to_world_normal(&normal)),
+            ..*triangle
+        })
+    }
+
+    fn from_faces(obj: &Obj, faces: &[FaceElement], triangulation: Triangulation) -> Self {
+        let mut model = Model::new();
+        // Faces without a material use the default one in front of the OBJ materials
+        let mut materials: HashMap<Option<usize>, usize> = HashMap::from([(None, 0)]);
+        for face in faces.iter() {
+            let material = *materials.entry(face.material).or_insert_with(|| {
+                model.materials.push(obj.materials[face.material.unwrap()].clone());
+                model.materials.len() - 1
+            });
+            // Vertex colours replace the diffuse colour of the material
+            let has_vertex_colors = face.vertex_indices.iter().all(|&i| obj.vertices[i].color.is_some());
+            let mut triangles = Triangle3D::create_triangles_from_face(obj, face, triangulation);
             for triangle in triangles.iter_mut() {
-                triangle.set_color(Float3::random());
+                triangle.material = material;
+                if !has_vertex_colors {
+                    triangle.set_color(model.materials[material].diffuse);
+                }
                 model.add_triangle(*triangle);
             }
         }
@@ -74,61 +294,42 @@ impl Model {
     }
 }
 
-pub fn render(model: &Model, target: &mut RenderTarget) {
-    target.clear();
+/// Draw a model into the frame as seen by `camera`, running the vertex stage
+/// of `shader` now and its fragment stage when the frame is rasterized into
+/// `target`.
+pub fn render<'a, S: Shader>(frame: &mut Frame<'a>, model: &Model, camera: &Camera, target: &RenderTarget, shader: &'a S) {
+    let uniforms = Uniforms {
+        transform: model.transform,
+        camera: *camera,
+        aspect: camera.aspect_for(target.width, target.height),
+    };
     
+    let mut screen_triangles = Vec::with_capacity(model.triangles.len());
     for triangle in model.triangles.iter() {
-        let a_screen = vertex_to_screen_space(&triangle.a, target, &model.transform);
-        let b_screen = vertex_to_screen_space(&triangle.b, target, &model.transform);
-        let c_screen = vertex_to_screen_space(&triangle.c, target, &model.transform);
-        let triangle = Triangle2D {
-            a: Float2 { x: a_screen.x, y: a_screen.y },
-            b: Float2 { x: b_screen.x, y: b_screen.y },
-            c: Float2 { x: c_screen.x, y: c_screen.y },
-            color: triangle.color,
-        };
-
-        let min_x = triangle.a.x.min(triangle.b.x).min(triangle.c.x);
-        let max_x = triangle.a.x.max(triangle.b.x).max(triangle.c.x);
-        let min_y = triangle.a.y.min(triangle.b.y).min(triangle.c.y);
-        let max_y = triangle.a.y.max(triangle.b.y).max(triangle.c.y);
-        
-        let block_start_x = min_x.floor().clamp(0.0, target.width as f32 - 1.0) as usize;
-        let block_end_x = max_x.ceil().clamp(0.0, target.width as f32 - 1.0) as usize;
-        let block_start_y = min_y.floor().clamp(0.0, target.height as f32 - 1.0) as usize;
-        let block_end_y = max_y.ceil().clamp(0.0, target.height as f32 - 1.0) as usize;
-
-        for y in block_start_y..=block_end_y {
-            for x in block_start_x..=block_end_x {
-                let p = Float2::new(x as f32, y as f32);
-                let (in_triangle, weight) = triangle.contains_point(p);
-                if in_triangle {
-                    let depths = Float3::new(
-                        a_screen.z,
-                        b_screen.z,
-                        c_screen.z, 
-                    );
-                    let depth = depths.dot(&weight);
-                    if depth > target.depth_buffer[y][x] {
-                        continue; // Skip this pixel if it's not closer than the current depth
-                    } 
-                    
-                    target.pixels[y][x] = triangle.color;    
-                    target.depth_buffer[y][x] = depth;
-                }
-            }
+        let clip_triangle_vertices = triangle.vertices().map(|vertex| {
+            let (position, attributes) = shader.vertex(&vertex, &uniforms);
+            ClipVertex { position, attributes }
+        });
+
+        for clipped in clip_triangle(clip_triangle_vertices) {
+            screen_triangles.extend(ScreenTriangle::new(
+                clipped.map(|vertex| clip_to_screen_space(vertex.position, target)),
+                clipped.map(|vertex| vertex.attributes),
+                triangle.material,
+                &target.render_state,
+                target.width,
+                target.height,
+            ));
         }
     }
-}
 
-fn vertex_to_screen_space(vertex : &Float3, target: &RenderTarget, transform: &Transform) -> Float3 {
-    let vertex_world = transform.to_world_point(vertex);
-    
-    let screen_height_world : f32 = (target.fov.to_radians() / 2.0).tan() * 2.0; 
-    let pixels_per_world_unit = target.height as f32 / screen_height_world / vertex_world.z;
+    frame.add(screen_triangles, |fragment| shader.fragment(fragment));
+}
 
-    let pixel_offset = Float2::new(vertex_world.x, vertex_world.y) * pixels_per_world_unit;
-    let vertex_screen = target.size() / 2.0 + pixel_offset;
-    Float3::new(vertex_screen.x, vertex_screen.y, vertex_world.z)
+/// Perform the perspective divide and map the result to pixel coordinates.
+/// The returned `z` is the view-space depth used for depth testing.
+fn clip_to_screen_space(clip: Float4, target: &RenderTarget) -> Float3 {
+    let ndc = Float2::new(clip.x, clip.y) / clip.w;
+    let vertex_screen = (ndc + 1.0) * (target.size() / 2.0);
+    Float3::new(vertex_screen.x, vertex_screen.y, clip.w)
 }
-    
\ No newline at end of file
@@ -1,10 +1,52 @@
-use crate::{render::Model, transform::Transform};
+use crate::{camera::Camera, float3::Float3, light::{Attenuation, Light}, raster::Frame, render::{self, Model, RenderTarget}, shader::{LitShader, UnlitShader}};
 
+#[derive(Default)]
 pub struct Scene {
-    pub entities: Vec<Entity>,
+    pub models: Vec<Model>,
+    pub lights: Vec<Light>,
+    pub camera: Camera,
 }
 
-pub struct Entity {
-    pub model: Model,
-    pub transform: Transform,
-}
\ No newline at end of file
+impl Scene {
+    /// A camera `distance` away from the origin, where models are shown, looking
+    /// at it along +Z.
+    pub fn default_camera(distance: f32) -> Camera {
+        Camera::looking_at(Float3::new(0.0, 0.0, -distance), Float3::zero())
+    }
+
+    /// The lights used to show models at the origin, one of each kind.
+    pub fn default_lights() -> Vec<Light> {
+        vec![
+            Light::Ambient { color: Float3::new(0.1, 0.1, 0.1) },
+            Light::Directional { direction: Float3::new(0.5, -1.0, 1.0), color: Float3::new(0.8, 0.8, 0.8) },
+            Light::Point { position: Float3::new(-3.0, 2.0, -3.0), color: Float3::new(0.6, 0.5, 0.3), attenuation: Attenuation::new(1.0, 0.1, 0.02) },
+            Light::Spot {
+                position: Float3::new(0.0, -4.0, -3.0),
+                direction: Float3::new(0.0, 1.0, 0.6),
+                color: Float3::new(0.2, 0.3, 0.6),
+                attenuation: Attenuation::new(1.0, 0.0, 0.0),
+                inner_angle: 15.0,
+                outer_angle: 30.0,
+            },
+        ]
+    }
+
+    /// Clear the target and draw every model into it as seen by the camera of
+    /// the scene, either lit or in flat colours.
+    pub fn draw(&self, target: &mut RenderTarget, unlit: bool) {
+        target.clear();
+        // The shaders are used until the whole frame is rasterized
+        let shaders: Vec<LitShader> = self.models.iter()
+            .map(|model| LitShader { lights: &self.lights, materials: &model.materials, eye: self.camera.position })
+            .collect();
+        let mut frame = Frame::default();
+        for (model, shader) in self.models.iter().zip(shaders.iter()) {
+            if unlit {
+                render::render(&mut frame, model, &self.camera, target, &UnlitShader);
+            } else {
+                render::render(&mut frame, model, &self.camera, target, shader);
+            }
+        }
+        frame.rasterize(target);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{camera::Camera, float2::Float2, float3::Float3, float4::Float4, interpolate::Interpolate, light::{shade_blinn_phong, Light, Surface}, material::{Material, TextureMap}, texture::{FilterMode, TextureCoordinate}, transform::Transform};
+
+/// The inputs of the vertex stage for one corner of a triangle, in object space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VertexInput {
+    pub position: Float3,
+    pub normal: Float3,
+    pub uv: Float2,
+    pub color: Float3, // Diffuse colour, from the vertex colours or else the material
+}
+
+/// The inputs of the fragment stage for one pixel.
+///
+/// Pixels are shaded in 2x2 quads and the derivatives are the differences of
+/// the varyings between the pixels of the quad, shared by all four of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FragmentInput<V> {
+    pub varyings: V,
+    pub ddx: V, // Change of the varyings to the next pixel in the row
+    pub ddy: V, // Change of the varyings to the next row
+    pub material: usize, // Index into the materials of the model
+}
+
+/// Values that are constant for a whole draw call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Uniforms {
+    pub transform: Transform, // Object to world transform of the model
+    pub camera: Camera,
+    pub aspect: f32, // Width divided by height of the image, from the camera or the render target
+}
+
+impl Uniforms {
+    pub fn object_to_world(&self, p: &Float3) -> Float3 {
+        self.transform.to_world_point(p)
+    }
+
+    pub fn normal_to_world(&self, n: &Float3) -> Float3 {
+        self.transform.to_world_normal(n)
+    }
+
+    /// Project a world-space point into homogeneous clip space, as seen by the camera.
+    pub fn world_to_clip(&self, p: &Float3) -> Float4 {
+        self.camera.view_to_clip(&self.camera.world_to_view(p), self.aspect)
+    }
+
+    /// Transform an object-space point into homogeneous clip space.
+    pub fn object_to_clip(&self, p: &Float3) -> Float4 {
+        self.world_to_clip(&self.object_to_world(p))
+    }
+}
+
+/// A programmable pair of vertex and fragment stages used by `render::render`.
+pub trait Shader: Sync {
+    /// Data passed from the vertex stage to the fragment stage, interpolated
+    /// across the triangle with perspective correction.
+    type Varyings: Interpolate + Sync;
+
+    /// Transform a vertex into clip space and compute its varyings.
+    fn vertex(&self, vertex: &VertexInput, uniforms: &Uniforms) -> (Float4, Self::Varyings);
+
+    /// Compute the colour of a pixel, or `None` to discard it.
+    fn fragment(&self, fragment: &FragmentInput<Self::Varyings>) -> Option<Float3>;
+}
+
+/// Draws every triangle in its flat colour, without any lighting.
+pub struct UnlitShader;
+
+impl Shader for UnlitShader {
+    type Varyings = Float3;
+
+    fn vertex(&self, vertex: &VertexInput, uniforms: &Uniforms) -> (Float4, Self::Varyings) {
+        (uniforms.object_to_clip(&vertex.position), vertex.color)
+    }
+
+    fn fragment(&self, fragment: &FragmentInput<Self::Varyings>) -> Option<Float3> {
+        Some(fragment.varyings)
+    }
+}
+
+/// Lights the model with Lambert diffuse and Blinn-Phong specular shading,
+/// using interpolated vertex normals and colours, and the material of each
+/// triangle.
+pub struct LitShader<'a> {
+    pub lights: &'a [Light],
+    pub materials: &'a [Material],
+    pub eye: Float3, // World-space position of the camera, for specular highlights
+}
+
+impl Shader for LitShader<'_> {
+    // World-space position and normal, texture coordinates and diffuse colour
+    type Varyings = (Float3, Float3, Float2, Float3);
+
+    fn vertex(&self, vertex: &VertexInput, uniforms: &Uniforms) -> (Float4, Self::Varyings) {
+        let position = uniforms.object_to_world(&vertex.position);
+        let normal = uniforms.normal_to_world(&vertex.normal);
+        (uniforms.world_to_clip(&position), (position, normal, vertex.uv, vertex.color))
+    }
+
+    fn fragment(&self, fragment: &FragmentInput<Self::Varyings>) -> Option<Float3> {
+        let (position, normal, uv, color) = fragment.varyings;
+        let material = &self.materials[fragment.material];
+        let uv = TextureCoordinate { uv, ddx: fragment.ddx.2, ddy: fragment.ddy.2 };
+        let filter = material.texture_filter;
+
+        // There is no blending, so mostly transparent pixels are cut out
+        let dissolve = material.dissolve * material.dissolve_map.as_ref().map_or(1.0, |map| map.sample_scalar(&uv, filter));
+        if dissolve < 0.5 {
+            return None;
+        }
+
+        let surface = Surface {
+            diffuse: color * sample_or_white(&material.diffuse_map, &uv, filter),
+            specular: material.specular * sample_or_white(&material.specular_map, &uv, filter),
+            shininess: material.shininess,
+        };
+        let emissive = material.emissive * sample_or_white(&material.emissive_map, &uv, filter);
+
+        let view_direction = (self.eye - position).normalize();
+        let color = match material.illumination_model {
+            0 => surface.diffuse, // Colour on and ambient off
+            1 => shade_blinn_phong(self.lights, &Surface { specular: Float3::zero(), ..surface }, position, normal.normalize(), view_direction),
+            _ => shade_blinn_phong(self.lights, &surface, position, normal.normalize(), view_direction),
+        };
+        Some(emissive + color)
+    }
+}
+
+// Texture colour of a map, or white if the material does not have the map
+fn sample_or_white(map: &Option<TextureMap>, uv: &TextureCoordinate, filter: FilterMode) -> Float3 {
+    map.as_ref().map_or(Float3::new(1.0, 1.0, 1.0), |map| map.sample(uv, filter))
+}
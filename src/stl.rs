@@ -0,0 +1,308 @@
+use std::io::{BufWriter, Write};
+
+use crate::{float3::Float3, render::Model};
+
+/// A triangle of an STL file with the normal stored for it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Facet {
+    pub normal: Float3,
+    pub vertices: [Float3; 3], // Counterclockwise when seen from the side the normal points to
+}
+
+/// The two encodings of STL files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StlFormat {
+    Ascii,
+    #[default]
+    Binary,
+}
+
+/// A triangle mesh in the STL format, as produced by most CAD tools.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stl {
+    pub name: String,
+    pub facets: Vec<Facet>,
+}
+
+// Size of the header and of a single facet of a binary STL file
+const BINARY_HEADER_SIZE: usize = 84;
+const BINARY_FACET_SIZE: usize = 50;
+
+impl Stl {
+    pub fn new() -> Self {
+        Stl { name: String::new(), facets: Vec::new() }
+    }
+
+    /// Read an ASCII or binary STL file.
+    ///
+    /// Binary files may also start with `solid`, so a file that starts with it
+    /// is only read as ASCII if it parses as such. Otherwise it is read as binary
+    /// if it holds at least the facets its header counts, anything after them is
+    /// ignored. Facets without a normal get the normal of their vertices.
+    pub fn read_from_file(file_path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(file_path).map_err(|e| format!("{}: {}", file_path, e))?;
+        let facet_count = bytes.get(80..BINARY_HEADER_SIZE).map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize);
+        let binary_size = facet_count.map(|count| BINARY_HEADER_SIZE + BINARY_FACET_SIZE * count);
+        let is_binary = binary_size.is_some_and(|size| bytes.len() >= size);
+
+        let ascii = if bytes.trim_ascii_start().starts_with(b"solid") {
+            let text = std::str::from_utf8(&bytes).map_err(|e| format!("{}: Invalid ASCII STL: {}", file_path, e));
+            Some(text.and_then(|text| Self::read_ascii(text, file_path)))
+        } else {
+            None
+        };
+
+        let mut result = match ascii {
+            // A binary header may start with solid too, then only a solid with facets counts
+            Some(Ok(stl)) if !stl.facets.is_empty() || !is_binary => stl,
+            _ if is_binary => Self::read_binary(&bytes, facet_count.unwrap()),
+            Some(Err(e)) => return Err(e),
+            _ => return Err(format!("{}: Neither an ASCII nor a binary STL file", file_path)),
+        };
+
+        for facet in result.facets.iter_mut() {
+            if facet.normal.length() == 0.0 {
+                let [a, b, c] = facet.vertices;
+                facet.normal = (b - a).cross(&(c - a)).normalize();
+            }
+        }
+        Ok(result)
+    }
+
+    fn read_binary(bytes: &[u8], facet_count: usize) -> Self {
+        let header = String::from_utf8_lossy(&bytes[..80]);
+        let mut result = Stl::new();
+        result.name = header.trim_end_matches(['\0', ' ']).to_string();
+
+        let facets = &bytes[BINARY_HEADER_SIZE..BINARY_HEADER_SIZE + BINARY_FACET_SIZE * facet_count];
+        for facet in facets.chunks_exact(BINARY_FACET_SIZE) {
+            let float = |offset: usize| f32::from_le_bytes(facet[offset..offset + 4].try_into().unwrap());
+            let vector = |offset: usize| Float3::new(float(offset), float(offset + 4), float(offset + 8));
+            // The trailing attribute byte count is ignored
+            result.facets.push(Facet {
+                normal: vector(0),
+                vertices: [vector(12), vector(24), vector(36)],
+            });
+        }
+        result
+    }
+
+    fn read_ascii(text: &str, file_path: &str) -> Result<Self, String> {
+        let mut result = Stl::new();
+        let mut tokens = Tokens::new(text, file_path);
+
+        // A file may contain several solids in a row, their facets are combined
+        while tokens.peek().is_some() {
+            let line = tokens.expect("solid")?;
+            let name = tokens.rest_of_line(line);
+            if result.name.is_empty() {
+                result.name = name;
+            }
+
+            loop {
+                match tokens.peek() {
+                    Some((_, token)) if token.eq_ignore_ascii_case("facet") => {
+                        tokens.expect("facet")?;
+                        tokens.expect("normal")?;
+                        let normal = tokens.vector()?;
+                        tokens.expect("outer")?;
+                        tokens.expect("loop")?;
+                        let mut vertices = [Float3::zero(); 3];
+                        for vertex in vertices.iter_mut() {
+                            tokens.expect("vertex")?;
+                            *vertex = tokens.vector()?;
+                        }
+                        tokens.expect("endloop")?;
+                        tokens.expect("endfacet")?;
+                        result.facets.push(Facet { normal, vertices });
+                    },
+                    Some((line, token)) if token.eq_ignore_ascii_case("endsolid") => {
+                        tokens.expect("endsolid")?;
+                        tokens.rest_of_line(line);
+                        break;
+                    },
+                    Some((line, token)) => return Err(tokens.error(line, format!("Expected 'facet' or 'endsolid' but found '{}'", token))),
+                    None => return Err(tokens.error(tokens.last_line, "Missing 'endsolid'".to_string())),
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Write the STL to a file in the given format.
+    pub fn write_to_file(&self, file_path: &str, format: StlFormat) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(std::fs::File::create(file_path)?);
+        match format {
+            StlFormat::Ascii => {
+                writeln!(writer, "solid {}", self.name)?;
+                for facet in self.facets.iter() {
+                    let n = facet.normal;
+                    writeln!(writer, "  facet normal {:e} {:e} {:e}", n.x, n.y, n.z)?;
+                    writeln!(writer, "    outer loop")?;
+                    for v in facet.vertices {
+                        writeln!(writer, "      vertex {:e} {:e} {:e}", v.x, v.y, v.z)?;
+                    }
+                    writeln!(writer, "    endloop")?;
+                    writeln!(writer, "  endfacet")?;
+                }
+                writeln!(writer, "endsolid {}", self.name)?;
+            },
+            StlFormat::Binary => {
+                // The header must not start with "solid", or readers may take it for ASCII
+                let mut header = [0u8; 80];
+                let text = match self.name.get(..5) {
+                    Some(start) if start.eq_ignore_ascii_case("solid") => format!("binary {}", self.name),
+                    _ => self.name.clone(),
+                };
+                let length = text.len().min(header.len());
+                header[..length].copy_from_slice(&text.as_bytes()[..length]);
+                writer.write_all(&header)?;
+                writer.write_all(&(self.facets.len() as u32).to_le_bytes())?;
+                for facet in self.facets.iter() {
+                    for v in [facet.normal, facet.vertices[0], facet.vertices[1], facet.vertices[2]] {
+                        for component in [v.x, v.y, v.z] {
+                            writer.write_all(&component.to_le_bytes())?;
+                        }
+                    }
+                    writer.write_all(&[0, 0])?; // Attribute byte count
+                }
+            },
+        }
+        writer.flush()
+    }
+
+    /// Convert a model into an STL with a facet for every triangle, placed by
+    /// the transform of the model. Vertex normals, texture coordinates and
+    /// materials are lost.
+    pub fn from_model(model: &Model) -> Self {
+        Stl {
+            name: model.name.clone(),
+            facets: model.world_triangles()
+                .map(|triangle| Facet { normal: triangle.normal(), vertices: [triangle.a, triangle.b, triangle.c] })
+                .collect(),
+        }
+    }
+}
+
+/// The whitespace separated tokens of an ASCII STL file with their line numbers.
+struct Tokens<'a> {
+    tokens: std::iter::Peekable<Box<dyn Iterator<Item = (usize, &'a str)> + 'a>>,
+    last_line: usize,
+    file_path: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str, file_path: &'a str) -> Self {
+        let tokens: Box<dyn Iterator<Item = (usize, &'a str)>> = Box::new(text.lines().enumerate()
+            .flat_map(|(index, line)| line.split_whitespace().map(move |token| (index + 1, token))));
+        Tokens { tokens: tokens.peekable(), last_line: 1, file_path }
+    }
+
+    fn peek(&mut self) -> Option<(usize, &'a str)> {
+        self.tokens.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<(usize, &'a str)> {
+        let token = self.tokens.next();
+        if let Some((line, _)) = token {
+            self.last_line = line;
+        }
+        token
+    }
+
+    // Consume a keyword, which is case insensitive, and return its line
+    fn expect(&mut self, keyword: &str) -> Result<usize, String> {
+        match self.next() {
+            Some((line, token)) if token.eq_ignore_ascii_case(keyword) => Ok(line),
+            Some((line, token)) => Err(self.error(line, format!("Expected '{}' but found '{}'", keyword, token))),
+            None => Err(self.error(self.last_line, format!("Expected '{}' but the file ended", keyword))),
+        }
+    }
+
+    fn vector(&mut self) -> Result<Float3, String> {
+        let mut number = || match self.next() {
+            Some((line, token)) => token.parse().map_err(|_| self.error(line, format!("Invalid number '{}'", token))),
+            None => Err(self.error(self.last_line, "Expected a number but the file ended".to_string())),
+        };
+        Ok(Float3::new(number()?, number()?, number()?))
+    }
+
+    fn error(&self, line: usize, message: String) -> String {
+        format!("{}:{}: {}", self.file_path, line, message)
+    }
+
+    // Consume the remaining tokens of a line, e.g. the name after "solid"
+    fn rest_of_line(&mut self, line: usize) -> String {
+        let mut words = Vec::new();
+        while let Some((_, word)) = self.tokens.next_if(|&(token_line, _)| token_line == line) {
+            words.push(word);
+        }
+        words.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_file::TempFile;
+
+    const FACET: &str = "facet normal 0 0 1\n outer loop\n  vertex 0 0 0\n  vertex 1 0 0\n  vertex 0 1 0\n endloop\nendfacet\n";
+
+    // Read `contents` as an STL file, with the path taken off the front of errors
+    fn read(name: &str, contents: &[u8]) -> Result<Stl, String> {
+        let file = TempFile::new(&format!("{}.stl", name), contents);
+        Stl::read_from_file(&file.path).map_err(|e| e.replacen(&file.path, "", 1))
+    }
+
+    // A binary STL with the given header text, one facet per triangle and extra bytes at the end
+    fn binary(header: &str, facets: usize, padding: usize) -> Vec<u8> {
+        let mut bytes = format!("{:<80}", header).into_bytes();
+        bytes.extend_from_slice(&(facets as u32).to_le_bytes());
+        for _ in 0..facets {
+            for value in [0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        bytes.resize(bytes.len() + padding, 0);
+        bytes
+    }
+
+    #[test]
+    fn reads_ascii_with_leading_whitespace() {
+        let stl = read("ascii", format!("\n  solid part\n{}{}endsolid part\n", FACET, FACET).as_bytes()).unwrap();
+        assert_eq!((stl.name.as_str(), stl.facets.len()), ("part", 2));
+    }
+
+    #[test]
+    fn reads_binary_with_padding_or_a_solid_header() {
+        assert_eq!(read("padding", &binary("model", 3, 0)).unwrap().facets.len(), 3);
+        assert_eq!(read("padding", &binary("model", 3, 7)).unwrap().facets.len(), 3);
+        let stl = read("padding", &binary("solid model", 2, 5)).unwrap();
+        assert_eq!((stl.name.as_str(), stl.facets.len()), ("solid model", 2));
+    }
+
+    #[test]
+    fn ascii_ending_early_reports_its_line() {
+        let error = |contents: String| read("early", contents.as_bytes()).unwrap_err();
+        assert_eq!(error(format!("solid a\n{}", &FACET[..40])), ":4: Expected a number but the file ended");
+        assert_eq!(error(format!("solid a\n{}", FACET)), ":8: Missing 'endsolid'");
+    }
+
+    #[test]
+    fn ascii_syntax_errors_report_their_line() {
+        let error = |contents: String| read("syntax", contents.as_bytes()).unwrap_err();
+        assert_eq!(error(format!("solid a\n{}", FACET.replace("vertex 1 0 0", "vertex 1 x 0"))), ":5: Invalid number 'x'");
+        assert_eq!(error(format!("solid a\n{}", FACET.replace("endloop", "loop"))), ":7: Expected 'endloop' but found 'loop'");
+        assert!(read("syntax", b"solid \xff\xfe\n").unwrap_err().starts_with(": Invalid ASCII STL"));
+    }
+
+    #[test]
+    fn short_binary_is_neither_format() {
+        // Fewer facets than the header counts, without the text of an ASCII file
+        let mut bytes = binary("model", 3, 0);
+        bytes.truncate(bytes.len() - 1);
+        assert_eq!(read("short", &bytes).unwrap_err(), ": Neither an ASCII nor a binary STL file");
+        assert_eq!(read("short", b"\x01\x02garbage").unwrap_err(), ": Neither an ASCII nor a binary STL file");
+    }
+}
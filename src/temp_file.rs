@@ -0,0 +1,21 @@
+/// A file in the temporary directory for a test to read, removed again when
+/// it is dropped.
+pub struct TempFile {
+    pub path: String,
+}
+
+impl TempFile {
+    /// `name` keeps apart the files of tests that run at the same time, and
+    /// ends with the extension the file should have.
+    pub fn new(name: &str, contents: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(format!("software_render_test_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).expect("The temporary file can be written");
+        TempFile { path: path.to_str().expect("The temporary directory is valid text").to_string() }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
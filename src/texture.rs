@@ -0,0 +1,219 @@
+use crate::{bitmap, png, float2::Float2, float3::Float3};
+
+/// How texture coordinates outside of 0..1 are mapped onto the texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressMode {
+    /// Tile the texture.
+    #[default]
+    Wrap,
+    /// Repeat the texels at the edges.
+    Clamp,
+    /// Tile the texture, flipping every other copy.
+    Mirror,
+}
+
+impl AddressMode {
+    // Map a texel coordinate onto 0..size
+    fn apply(&self, coordinate: i64, size: usize) -> usize {
+        let size = size as i64;
+        match self {
+            AddressMode::Wrap => coordinate.rem_euclid(size) as usize,
+            AddressMode::Clamp => coordinate.clamp(0, size - 1) as usize,
+            AddressMode::Mirror => {
+                let period = coordinate.rem_euclid(2 * size);
+                (if period < size { period } else { 2 * size - 1 - period }) as usize
+            },
+        }
+    }
+}
+
+/// How texels are filtered when a texture is sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterMode {
+    /// The nearest texel of the nearest mip level.
+    Nearest,
+    /// Blend the four nearest texels of the nearest mip level.
+    Bilinear,
+    /// Blend bilinear samples of the two nearest mip levels.
+    #[default]
+    Trilinear,
+    /// Average several trilinear samples along the direction in which the
+    /// texture is stretched the most, so surfaces seen at grazing angles stay sharp.
+    Anisotropic,
+}
+
+impl std::str::FromStr for FilterMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(FilterMode::Nearest),
+            "bilinear" => Ok(FilterMode::Bilinear),
+            "trilinear" => Ok(FilterMode::Trilinear),
+            "anisotropic" => Ok(FilterMode::Anisotropic),
+            _ => Err(format!("Unknown filter mode '{}', expected nearest, bilinear, trilinear or anisotropic", s)),
+        }
+    }
+}
+
+/// The most samples taken along the major axis by anisotropic filtering.
+const MAX_ANISOTROPY: f32 = 16.0;
+
+/// A texture coordinate together with how much it changes to the next pixel
+/// on the right (`ddx`) and on the next row (`ddy`). The derivatives select
+/// the mip level, so a texture far away is not undersampled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureCoordinate {
+    pub uv: Float2,
+    pub ddx: Float2,
+    pub ddy: Float2,
+}
+
+/// An image that is mapped onto surfaces using texture coordinates.
+///
+/// A full chain of mipmaps is generated when the texture is created, each
+/// level half the size of the previous one down to a single texel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    levels: Vec<MipLevel>, // Level 0 is the full size image
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<Float3>, // Row by row from the top
+}
+
+impl MipLevel {
+    fn texel(&self, x: i64, y: i64, address_mode: AddressMode) -> Float3 {
+        let x = address_mode.apply(x, self.width);
+        let y = address_mode.apply(y, self.height);
+        self.texels[y * self.width + x]
+    }
+
+    // Average blocks of 2x2 texels into a level of half the size. The last
+    // row or column of an odd sized level is folded into its neighbour.
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (x, y) = (2 * x as i64, 2 * y as i64);
+                let sum = self.texel(x, y, AddressMode::Clamp)
+                    + self.texel(x + 1, y, AddressMode::Clamp)
+                    + self.texel(x, y + 1, AddressMode::Clamp)
+                    + self.texel(x + 1, y + 1, AddressMode::Clamp);
+                texels.push(sum * 0.25);
+            }
+        }
+        MipLevel { width, height, texels }
+    }
+
+    fn nearest(&self, uv: Float2, address_mode: AddressMode) -> Float3 {
+        let x = (uv.x * self.width as f32).floor() as i64;
+        let y = ((1.0 - uv.y) * self.height as f32).floor() as i64;
+        self.texel(x, y, address_mode)
+    }
+
+    fn bilinear(&self, uv: Float2, address_mode: AddressMode) -> Float3 {
+        // Texel centres sit at half-integer positions
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0, address_mode) * (1.0 - tx) + self.texel(x0 + 1, y0, address_mode) * tx;
+        let bottom = self.texel(x0, y0 + 1, address_mode) * (1.0 - tx) + self.texel(x0 + 1, y0 + 1, address_mode) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+impl Texture {
+    pub fn new(width: usize, height: usize, texels: Vec<Float3>) -> Self {
+        assert_eq!(texels.len(), width * height, "Texel count does not match the texture size");
+        let mut levels = vec![MipLevel { width, height, texels }];
+        while let Some(level) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+            levels.push(level.downsample());
+        }
+        Texture { width, height, levels }
+    }
+
+    /// Load a texture from a BMP or PNG file.
+    pub fn read_from_file(file_path: &str) -> Result<Self, String> {
+        let data = std::fs::read(file_path).map_err(|e| format!("{}: {}", file_path, e))?;
+        Self::decode(&data).map_err(|e| format!("{}: {}", file_path, e))
+    }
+
+    /// Decode a BMP or PNG image, telling them apart by their signature.
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let (width, height, pixels) = if data.starts_with(b"BM") {
+            bitmap::decode_bmp(data)?
+        } else {
+            png::decode_png(data)?
+        };
+        let texels = pixels.iter()
+            .map(|&[r, g, b, _]| Float3::new(r as f32, g as f32, b as f32) * (1.0 / 255.0))
+            .collect();
+        Ok(Texture::new(width, height, texels))
+    }
+
+    /// Sample the texture at a texture coordinate, where `(0, 0)` is the
+    /// bottom-left corner of the image and `(1, 1)` the top-right corner.
+    pub fn sample(&self, coordinate: &TextureCoordinate, address_mode: AddressMode, filter: FilterMode) -> Float3 {
+        // Footprint of the pixel in texels along both screen axes
+        let size = Float2::new(self.width as f32, self.height as f32);
+        let length_x = (coordinate.ddx * size).length();
+        let length_y = (coordinate.ddy * size).length();
+        let uv = coordinate.uv;
+
+        match filter {
+            FilterMode::Nearest => self.level(self.level_of_detail(length_x.max(length_y)).round()).nearest(uv, address_mode),
+            FilterMode::Bilinear => self.level(self.level_of_detail(length_x.max(length_y)).round()).bilinear(uv, address_mode),
+            FilterMode::Trilinear => self.trilinear(uv, self.level_of_detail(length_x.max(length_y)), address_mode),
+            FilterMode::Anisotropic => {
+                // Take the level of detail from the minor axis and make up for
+                // the blur along the major axis with extra samples
+                let (major, major_length, minor_length) = if length_x >= length_y {
+                    (coordinate.ddx, length_x, length_y)
+                } else {
+                    (coordinate.ddy, length_y, length_x)
+                };
+                let samples = (major_length / minor_length.max(f32::EPSILON)).clamp(1.0, MAX_ANISOTROPY).ceil();
+                let level_of_detail = self.level_of_detail(major_length / samples);
+
+                let count = samples as usize;
+                let mut sum = Float3::zero();
+                for i in 0..count {
+                    // Spread the samples evenly across the footprint
+                    let offset = (i as f32 + 0.5) / samples - 0.5;
+                    sum = sum + self.trilinear(uv + major * offset, level_of_detail, address_mode);
+                }
+                sum * (1.0 / samples)
+            },
+        }
+    }
+
+    // Mip level whose texels are about the size of a pixel footprint of
+    // `length` texels, as a fractional level index
+    fn level_of_detail(&self, length: f32) -> f32 {
+        let level_of_detail = length.log2();
+        // Derivatives can be undefined where the quad extends past the horizon
+        if level_of_detail.is_nan() { 0.0 } else { level_of_detail.clamp(0.0, (self.levels.len() - 1) as f32) }
+    }
+
+    fn level(&self, level_of_detail: f32) -> &MipLevel {
+        &self.levels[level_of_detail as usize]
+    }
+
+    fn trilinear(&self, uv: Float2, level_of_detail: f32, address_mode: AddressMode) -> Float3 {
+        let fine = self.level(level_of_detail.floor());
+        let coarse = self.level(level_of_detail.ceil());
+        let t = level_of_detail.fract();
+        fine.bilinear(uv, address_mode) * (1.0 - t) + coarse.bilinear(uv, address_mode) * t
+    }
+}
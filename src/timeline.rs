@@ -0,0 +1,224 @@
+use crate::{float3::Float3, interpolate::Interpolate, transform::Transform};
+
+/// How a value moves from one keyframe to the next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    /// Keep the value until the next keyframe.
+    Step,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    /// A timing curve through (0, 0), the two control points and (1, 1), like
+    /// `cubic-bezier(x1, y1, x2, y2)` in CSS.
+    CubicBezier(f32, f32, f32, f32),
+}
+
+impl Interpolation {
+    /// Map the fraction of the time between two keyframes that has passed onto
+    /// the fraction of the way the value has moved.
+    pub fn ease(&self, t: f32) -> f32 {
+        match *self {
+            Interpolation::Step => 0.0,
+            Interpolation::Linear => t,
+            Interpolation::EaseIn => cubic_bezier(0.42, 0.0, 1.0, 1.0, t),
+            Interpolation::EaseOut => cubic_bezier(0.0, 0.0, 0.58, 1.0, t),
+            Interpolation::EaseInOut => cubic_bezier(0.42, 0.0, 0.58, 1.0, t),
+            Interpolation::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+        }
+    }
+}
+
+// The y of the curve where its x equals t. The x of the control points lies in
+// 0..1, so x grows with the curve parameter and can be found by bisection.
+fn cubic_bezier(x1: f32, y1: f32, x2: f32, y2: f32, t: f32) -> f32 {
+    let curve = |a: f32, b: f32, s: f32| 3.0 * (1.0 - s) * (1.0 - s) * s * a + 3.0 * (1.0 - s) * s * s * b + s * s * s;
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..32 {
+        let middle = (low + high) / 2.0;
+        if curve(x1, x2, middle) < t { low = middle } else { high = middle }
+    }
+    curve(y1, y2, (low + high) / 2.0)
+}
+
+/// The part of a transform a track animates. Angles are in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Property {
+    Position,
+    Yaw,
+    Pitch,
+    Roll,
+    Scale,
+}
+
+impl Property {
+    // Number of values a keyframe of this property has
+    fn components(&self) -> usize {
+        if *self == Property::Position { 3 } else { 1 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    pub time: f32, // In seconds
+    pub value: Float3, // Properties with a single value only use x
+    pub interpolation: Interpolation, // How the value moves on to the next keyframe
+}
+
+/// The keyframes of one property, ordered by time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub property: Property,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Track {
+    /// The value at a time. Before the first and after the last keyframe the
+    /// value of that keyframe is held.
+    pub fn sample(&self, time: f32) -> Float3 {
+        let next = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        match (next.checked_sub(1).map(|i| &self.keyframes[i]), self.keyframes.get(next)) {
+            (Some(from), Some(to)) => {
+                let t = (time - from.time) / (to.time - from.time);
+                Float3::lerp(from.value, to.value, from.interpolation.ease(t))
+            },
+            (Some(keyframe), None) | (None, Some(keyframe)) => keyframe.value,
+            (None, None) => Float3::zero(),
+        }
+    }
+}
+
+/// The tracks that animate the models with a given name, or all models for `*`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityTimeline {
+    pub entity: String,
+    pub tracks: Vec<Track>,
+}
+
+/// Keyframed transforms for the models of a scene.
+///
+/// Timelines are read from text files with one statement per line and `#`
+/// starting a comment:
+///
+/// ```text
+/// duration 10               # Length of the timeline in seconds
+/// entity monke              # Following keys animate models named monke, * for all
+/// key 0 position 0 0 0      # key <time> <property> <values> [interpolation]
+/// key 2 position 0 1 0 ease-in-out
+/// key 0 yaw 0 bezier 0.25 0.1 0.25 1
+/// key 4 yaw 360
+/// ```
+///
+/// The properties are `position`, `yaw`, `pitch` and `roll` in degrees, and
+/// `scale`. The interpolation of a key, `step`, `linear` (the default),
+/// `ease-in`, `ease-out`, `ease-in-out` or `bezier x1 y1 x2 y2`, is how the
+/// value moves on to the next key. Properties without keys are left alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub duration: f32, // In seconds, the time of the last keyframe unless given
+    pub entities: Vec<EntityTimeline>,
+}
+
+impl Default for Timeline {
+    /// Turns the models around and tips them over, with pauses in between.
+    fn default() -> Self {
+        Timeline::parse(include_str!("../assets/turntable.timeline"), "assets/turntable.timeline").expect("The default timeline is valid")
+    }
+}
+
+impl Timeline {
+    pub fn read_from_file(file_path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(file_path).map_err(|e| format!("{}: {}", file_path, e))?;
+        Self::parse(&text, file_path)
+    }
+
+    fn parse(text: &str, file_path: &str) -> Result<Self, String> {
+        let mut duration = None;
+        let mut entities: Vec<EntityTimeline> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let context = |message: String| format!("{}:{}: {}", file_path, index + 1, message);
+            let words: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+            let number = |word: &str| word.parse::<f32>().ok().filter(|n| n.is_finite()).ok_or_else(|| context(format!("Invalid number '{}'", word)));
+            match words.as_slice() {
+                [] => {},
+                ["duration", value] => match number(value)? {
+                    value if value > 0.0 => duration = Some(value),
+                    _ => return Err(context("The duration must be positive".to_string())),
+                },
+                ["entity", name] => entities.push(EntityTimeline { entity: name.to_string(), tracks: Vec::new() }),
+                ["key", time, property, rest @ ..] => {
+                    let entity = entities.last_mut().ok_or_else(|| context("Key before any entity".to_string()))?;
+                    let property = match *property {
+                        "position" => Property::Position,
+                        "yaw" => Property::Yaw,
+                        "pitch" => Property::Pitch,
+                        "roll" => Property::Roll,
+                        "scale" => Property::Scale,
+                        _ => return Err(context(format!("Unknown property '{}'", property))),
+                    };
+                    let components = property.components();
+                    if rest.len() < components {
+                        return Err(context(format!("Expected {} values", components)));
+                    }
+                    let values = rest[..components].iter().map(|word| number(word)).collect::<Result<Vec<_>, _>>()?;
+                    let value = Float3::new(values[0], values.get(1).copied().unwrap_or(0.0), values.get(2).copied().unwrap_or(0.0));
+                    let interpolation = match &rest[components..] {
+                        [] | ["linear"] => Interpolation::Linear,
+                        ["step"] => Interpolation::Step,
+                        ["ease-in"] => Interpolation::EaseIn,
+                        ["ease-out"] => Interpolation::EaseOut,
+                        ["ease-in-out"] => Interpolation::EaseInOut,
+                        ["bezier", x1, y1, x2, y2] => {
+                            let (x1, x2) = (number(x1)?, number(x2)?);
+                            if !(0.0..=1.0).contains(&x1) || !(0.0..=1.0).contains(&x2) {
+                                return Err(context("The x of the bezier control points must be between 0 and 1".to_string()));
+                            }
+                            Interpolation::CubicBezier(x1, number(y1)?, x2, number(y2)?)
+                        },
+                        other => return Err(context(format!("Unknown interpolation '{}'", other.join(" ")))),
+                    };
+
+                    let keyframe = Keyframe { time: number(time)?, value, interpolation };
+                    let track = match entity.tracks.iter().position(|track| track.property == property) {
+                        Some(track) => &mut entity.tracks[track],
+                        None => {
+                            entity.tracks.push(Track { property, keyframes: Vec::new() });
+                            entity.tracks.last_mut().unwrap()
+                        },
+                    };
+                    // Keys may be written in any order, a key at the same time replaces the earlier one
+                    let index = track.keyframes.partition_point(|other| other.time < keyframe.time);
+                    match track.keyframes.get_mut(index) {
+                        Some(other) if other.time == keyframe.time => *other = keyframe,
+                        _ => track.keyframes.insert(index, keyframe),
+                    }
+                },
+                _ => return Err(context(format!("Invalid statement '{}'", words.join(" ")))),
+            }
+        }
+
+        let last_key = entities.iter()
+            .flat_map(|entity| entity.tracks.iter())
+            .filter_map(|track| track.keyframes.last())
+            .fold(0.0, |last: f32, keyframe| last.max(keyframe.time));
+        Ok(Timeline { duration: duration.unwrap_or(last_key), entities })
+    }
+
+    /// Set the animated properties of the transform of a model at a time. The
+    /// timeline repeats after its duration.
+    pub fn apply(&self, name: &str, transform: &mut Transform, time: f32) {
+        let time = if self.duration > 0.0 { time.rem_euclid(self.duration) } else { time };
+        for entity in self.entities.iter().filter(|entity| entity.entity == "*" || entity.entity == name) {
+            for track in entity.tracks.iter() {
+                let value = track.sample(time);
+                match track.property {
+                    Property::Position => transform.position = value,
+                    Property::Yaw => transform.yaw = value.x.to_radians(),
+                    Property::Pitch => transform.pitch = value.x.to_radians(),
+                    Property::Roll => transform.roll = value.x.to_radians(),
+                    Property::Scale => transform.scale = value.x,
+                }
+            }
+        }
+    }
+}
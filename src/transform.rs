@@ -5,14 +5,44 @@ use crate::float3::Float3;
 pub struct Transform {
     pub yaw: f32,
     pub pitch: f32,
+    pub roll: f32, // Rotation around the Z axis, applied before pitch and yaw
+    pub scale: f32, // Uniform scale, applied before rotating
     pub position: Float3,
+    pub placement: Matrix4, // Applied before everything else, e.g. the transform of a glTF node
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform { yaw: 0.0, pitch: 0.0, roll: 0.0, scale: 1.0, position: Float3::zero(), placement: Matrix4::identity() }
+    }
 }
 
 impl Transform {
 
-    pub fn to_world_point(&self, p: &Float3) -> Float3 {
+    pub fn to_world_point(self, p: &Float3) -> Float3 {
+        let (i_hat, j_hat, k_hat) = self.get_basis_vectors();
+        let p = self.placement.transform_point(p);
+        Transform::transform_vector(i_hat, j_hat, k_hat, &(p * self.scale)) + self.position
+    }
+
+    // Rotate a direction without moving or scaling it, leaving out the placement
+    pub fn to_world_direction(self, v: &Float3) -> Float3 {
+        let (i_hat, j_hat, k_hat) = self.get_basis_vectors();
+        Transform::transform_vector(i_hat, j_hat, k_hat, v)
+    }
+
+    // Turn a normal along with the surface it belongs to, keeping it unit length
+    pub fn to_world_normal(self, n: &Float3) -> Float3 {
+        self.to_world_direction(&self.placement.transform_normal(n))
+    }
+
+    // Undo `to_world_point`, giving the coordinates of a world point in the
+    // space of the transform
+    pub fn to_local_point(self, p: &Float3) -> Float3 {
         let (i_hat, j_hat, k_hat) = self.get_basis_vectors();
-        Transform::transform_vector(i_hat, j_hat, k_hat, p) + self.position
+        let offset = *p - self.position;
+        let p = Float3::new(offset.dot(&i_hat), offset.dot(&j_hat), offset.dot(&k_hat)) * (1.0 / self.scale);
+        self.placement.inverse().transform_point(&p)
     }
 
     fn get_basis_vectors(&self) -> (Float3, Float3, Float3) {
@@ -26,16 +56,104 @@ impl Transform {
         let j_hat_pitch = Float3::new(0.0, self.pitch.cos(), -self.pitch.sin());
         let k_hat_pitch = Float3::new(0.0, self.pitch.sin(), self.pitch.cos());
 
-        // Combine yaw and pitch to get the final basis vectors
+        // Roll
+        let i_hat_roll = Float3::new(self.roll.cos(), self.roll.sin(), 0.0);
+        let j_hat_roll = Float3::new(-self.roll.sin(), self.roll.cos(), 0.0);
+        let k_hat_roll = Float3::new(0.0, 0.0, 1.0);
+
+        // Combine yaw and pitch, then roll to get the final basis vectors
         let i_hat = Self::transform_vector(i_hat_yaw, j_hat_yaw, k_hat_yaw, &i_hat_pitch);
         let j_hat = Self::transform_vector(i_hat_yaw, j_hat_yaw, k_hat_yaw, &j_hat_pitch);
         let k_hat = Self::transform_vector(i_hat_yaw, j_hat_yaw, k_hat_yaw, &k_hat_pitch);
-
-        (i_hat, j_hat, k_hat)
+        (
+            Self::transform_vector(i_hat, j_hat, k_hat, &i_hat_roll),
+            Self::transform_vector(i_hat, j_hat, k_hat, &j_hat_roll),
+            Self::transform_vector(i_hat, j_hat, k_hat, &k_hat_roll),
+        )
     }
 
     // Move each coordinate of the given vector along the corresponding basis vector
     fn transform_vector(i_hat : Float3, j_hat: Float3, k_hat: Float3, v: &Float3) -> Float3 {
         v.x * i_hat + v.y * j_hat + v.z * k_hat
     }
-}
\ No newline at end of file
+}
+
+/// A 4x4 affine transformation, stored as columns like in glTF.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    pub columns: [[f32; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Self {
+        Matrix4 { columns: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]] }
+    }
+
+    /// Build a matrix that scales, then rotates by a unit quaternion `[x, y, z, w]`, then translates.
+    pub fn from_translation_rotation_scale(translation: Float3, rotation: [f32; 4], scale: Float3) -> Self {
+        let [x, y, z, w] = rotation;
+        let rotation = [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + z * w), 2.0 * (x * z - y * w)],
+            [2.0 * (x * y - z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + x * w)],
+            [2.0 * (x * z + y * w), 2.0 * (y * z - x * w), 1.0 - 2.0 * (x * x + y * y)],
+        ];
+        let scale = [scale.x, scale.y, scale.z];
+        let mut columns = Self::identity().columns;
+        for (column, (axis, scale)) in columns.iter_mut().zip(rotation.iter().zip(scale)) {
+            *column = [axis[0] * scale, axis[1] * scale, axis[2] * scale, 0.0];
+        }
+        columns[3] = [translation.x, translation.y, translation.z, 1.0];
+        Matrix4 { columns }
+    }
+
+    /// The transformation that applies `other` first and then `self`.
+    pub fn multiply(&self, other: &Matrix4) -> Matrix4 {
+        let columns = other.columns.map(|column| {
+            std::array::from_fn(|row| (0..4).map(|i| self.columns[i][row] * column[i]).sum())
+        });
+        Matrix4 { columns }
+    }
+
+    pub fn transform_point(&self, p: &Float3) -> Float3 {
+        self.transform_direction(p) + self.column(3)
+    }
+
+    // Apply the linear part only, leaving out the translation
+    pub fn transform_direction(&self, v: &Float3) -> Float3 {
+        v.x * self.column(0) + v.y * self.column(1) + v.z * self.column(2)
+    }
+
+    /// Transform a normal so it stays perpendicular to the transformed surface,
+    /// using the inverse transpose of the linear part. The result is normalised.
+    pub fn transform_normal(&self, n: &Float3) -> Float3 {
+        // The cofactor matrix is the inverse transpose scaled by the determinant
+        let (x, y, z) = (self.column(0), self.column(1), self.column(2));
+        let (cx, cy, cz) = (y.cross(&z), z.cross(&x), x.cross(&y));
+        ((n.x * cx + n.y * cy + n.z * cz) * self.determinant().signum()).normalize()
+    }
+
+    /// The inverse transformation, assuming the matrix is affine and not
+    /// degenerate.
+    pub fn inverse(&self) -> Matrix4 {
+        // The rows of the inverse of the linear part are its cofactors divided by the determinant
+        let (x, y, z) = (self.column(0), self.column(1), self.column(2));
+        let [a, b, c] = [y.cross(&z), z.cross(&x), x.cross(&y)].map(|row| row * (1.0 / self.determinant()));
+        let translation = self.column(3);
+        Matrix4 { columns: [
+            [a.x, b.x, c.x, 0.0],
+            [a.y, b.y, c.y, 0.0],
+            [a.z, b.z, c.z, 0.0],
+            [-a.dot(&translation), -b.dot(&translation), -c.dot(&translation), 1.0],
+        ] }
+    }
+
+    /// The determinant of the linear part, negative if the transformation mirrors.
+    pub fn determinant(&self) -> f32 {
+        self.column(0).dot(&self.column(1).cross(&self.column(2)))
+    }
+
+    fn column(&self, index: usize) -> Float3 {
+        let [x, y, z, _] = self.columns[index];
+        Float3::new(x, y, z)
+    }
+}
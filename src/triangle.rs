@@ -2,87 +2,93 @@
 use crate::float2::Float2;
 use crate::float3::Float3;
 use crate::obj::{FaceElement, Obj};
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct Triangle2D {
-    pub a: Float2,
-    pub b: Float2,
-    pub c: Float2,
-    pub color: Float3, // Optional color field
-}
+use crate::shader::VertexInput;
+use crate::triangulate::{triangulate, Triangulation};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Triangle3D {
     pub a: Float3,
     pub b: Float3,
     pub c: Float3,
-    pub color: Float3, // Optional color field
+    pub normals: [Float3; 3], // Vertex normals of a, b and c
+    pub uvs: [Float2; 3], // Texture coordinates of a, b and c
+    pub colors: [Float3; 3], // Diffuse colours of a, b and c
+    pub material: usize, // Index into the materials of the model
 }
 
-impl Triangle2D {
-    pub fn new(a: Float2, b: Float2, c: Float2) -> Self {
-        Triangle2D { a, b, c, color: Float3::new(1.0, 1.0, 1.0) }
-    }
-
-    pub fn contains_point(&self, p: Float2) -> (bool, Float3) {
-        let area_abp = Self::triangle_area(self.a, self.b, p);
-        let area_bcp = Self::triangle_area(self.b, self.c, p);
-        let area_cap = Self::triangle_area(self.c, self.a, p);
-        let in_triangle = area_abp >= 0.0 && area_bcp >= 0.0 && area_cap >= 0.0;
-
-        let total_area = area_abp + area_bcp + area_cap;
-        let inverse_area = 1.0 / total_area;
-        let weight = Float3::new(
-            area_bcp * inverse_area,
-            area_cap * inverse_area,
-            area_abp * inverse_area,
-        );
-        
-        (in_triangle && total_area > 0.0, weight)
+impl Triangle3D {
+    // Create a triangle with flat shading, i.e. all vertex normals set to the face normal
+    pub fn new(a: Float3, b: Float3, c: Float3) -> Self {
+        let mut triangle = Triangle3D {
+            a,
+            b,
+            c,
+            normals: [Float3::zero(); 3],
+            uvs: [Float2::new(0.0, 0.0); 3],
+            colors: [Float3::new(1.0, 1.0, 1.0); 3],
+            material: 0,
+        };
+        triangle.normals = [triangle.normal(); 3];
+        triangle
     }
 
     pub fn set_color(&mut self, color: Float3) {
-        self.color = color;
+        self.colors = [color; 3];
     }
 
-    pub fn triangle_area(a : Float2, b: Float2, c: Float2) -> f32 {
-        let ac = c - a;
-        let ab = b - a;
-        let ab_perpendicular = ab.rotate_clockwise();
-        ac.dot(&ab_perpendicular) / 2.0
+    // Unit normal of the plane of the triangle, facing the side the corners appear counterclockwise from
+    pub fn normal(&self) -> Float3 {
+        (self.b - self.a).cross(&(self.c - self.a)).normalize()
     }
-}
 
-impl Triangle3D {
-    pub fn new(a: Float3, b: Float3, c: Float3) -> Self {
-        Triangle3D { a, b, c, color: Float3::new(1.0, 1.0, 1.0) }
-    }
-
-    pub fn set_color(&mut self, color: Float3) {
-        self.color = color;
+    /// The corners of the triangle as inputs for a vertex shader.
+    pub fn vertices(&self) -> [VertexInput; 3] {
+        let positions = [self.a, self.b, self.c];
+        std::array::from_fn(|i| VertexInput {
+            position: positions[i],
+            normal: self.normals[i],
+            uv: self.uvs[i],
+            color: self.colors[i],
+        })
     }
 
-    pub fn create_triangles_from_face(obj : &Obj, face : &FaceElement) -> Vec<Triangle3D> {
-        let mut triangles = Vec::new();
+    /// Split a face of an OBJ file into triangles with the given triangulation.
+    ///
+    /// The triangles are white unless the vertices of the face have colours.
+    pub fn create_triangles_from_face(obj : &Obj, face : &FaceElement, triangulation: Triangulation) -> Vec<Triangle3D> {
         if face.vertex_indices.len() < 3 {
-            return triangles; // Not enough vertices to form a triangle
+            return Vec::new(); // Not enough vertices to form a triangle
         }
 
-        // Create first triangle
-        let a = obj.vertices[face.vertex_indices[0]].position;
-        let b = obj.vertices[face.vertex_indices[1]].position;
-        let c = obj.vertices[face.vertex_indices[2]].position;    
-        let mut triangle = Triangle3D::new(a, b, c);
+        let positions: Vec<Float3> = face.vertex_indices.iter().map(|&i| obj.vertices[i].position).collect();
+        // Vertex normals from the file, if every corner of the face has one
+        let normals: Option<Vec<Float3>> = face.normal_indices.as_ref()
+            .filter(|indices| indices.len() == face.vertex_indices.len())
+            .map(|indices| indices.iter().map(|&i| obj.normals[i]).collect());
+        let uvs: Option<Vec<Float2>> = face.texture_indices.as_ref()
+            .filter(|indices| indices.len() == face.vertex_indices.len())
+            .map(|indices| indices.iter().map(|&i| {
+                let texture_coordinate = obj.texture_coordinates[i];
+                Float2::new(texture_coordinate.x, texture_coordinate.y)
+            }).collect());
 
-        triangles.push(triangle.clone());
-        // Create additional triangles for polygons with more than 3 vertices
-        for i in 3 .. face.vertex_indices.len() {
-            triangle.b = triangle.c;
-            triangle.c = obj.vertices[face.vertex_indices[i]].position;
-            triangles.push(triangle.clone());
-        }
-    
-        triangles
+        // Vertex colours, if every corner has one
+        let colors: Option<Vec<Float3>> = face.vertex_indices.iter().map(|&i| obj.vertices[i].color).collect();
+
+        triangulate(&positions, triangulation).into_iter().map(|corners| {
+            let [a, b, c] = corners.map(|i| positions[i]);
+            let mut triangle = Triangle3D::new(a, b, c);
+            if let Some(normals) = &normals {
+                triangle.normals = corners.map(|i| normals[i]);
+            }
+            if let Some(uvs) = &uvs {
+                triangle.uvs = corners.map(|i| uvs[i]);
+            }
+            if let Some(colors) = &colors {
+                triangle.colors = corners.map(|i| colors[i]);
+            }
+            triangle
+        }).collect()
     }
     
 }
@@ -0,0 +1,110 @@
+use crate::{float2::Float2, float3::Float3};
+
+/// How polygons with more than three corners are split into triangles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Triangulation {
+    /// Connect the first corner to every other edge. Only correct for convex
+    /// polygons, but cheap and stable.
+    #[default]
+    Fan,
+    /// Clip ears off the polygon after projecting it onto its best-fit plane,
+    /// which also handles concave polygons. Falls back to a fan for polygons
+    /// that have no area or intersect themselves.
+    EarClipping,
+}
+
+impl std::str::FromStr for Triangulation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fan" => Ok(Triangulation::Fan),
+            "ear" | "earclipping" | "ear-clipping" => Ok(Triangulation::EarClipping),
+            _ => Err(format!("Unknown triangulation '{}', expected fan or ear", s)),
+        }
+    }
+}
+
+/// Normal of the best-fit plane of a polygon using Newell's method, which also
+/// works for concave and slightly non-planar polygons.
+///
+/// The length of the normal is twice the area of the polygon, and it faces the
+/// side the corners appear counterclockwise from.
+pub fn polygon_normal(positions: &[Float3]) -> Float3 {
+    let mut normal = Float3::zero();
+    for (i, &current) in positions.iter().enumerate() {
+        let next = positions[(i + 1) % positions.len()];
+        normal = normal + Float3::new(
+            (current.y - next.y) * (current.z + next.z),
+            (current.z - next.z) * (current.x + next.x),
+            (current.x - next.x) * (current.y + next.y),
+        );
+    }
+    normal
+}
+
+/// Split a polygon into triangles, returned as indices of its corners.
+///
+/// The triangles keep the winding order of the polygon.
+pub fn triangulate(positions: &[Float3], triangulation: Triangulation) -> Vec<[usize; 3]> {
+    let corners: Vec<usize> = (0..positions.len()).collect();
+    match triangulation {
+        _ if positions.len() <= 3 => fan(&corners),
+        Triangulation::Fan => fan(&corners),
+        Triangulation::EarClipping => ear_clip(positions).unwrap_or_else(|| fan(&corners)),
+    }
+}
+
+fn fan(corners: &[usize]) -> Vec<[usize; 3]> {
+    (2..corners.len()).map(|i| [corners[0], corners[i - 1], corners[i]]).collect()
+}
+
+// Ear clipping in the best-fit plane. Returns `None` if the polygon has no area.
+// If no ear can be found, e.g. because the polygon intersects itself, the
+// rest of the polygon is fanned.
+fn ear_clip(positions: &[Float3]) -> Option<Vec<[usize; 3]>> {
+    let normal = polygon_normal(positions);
+    if normal.length() <= f32::EPSILON {
+        return None;
+    }
+
+    // Project onto two axes in the plane, ordered so the polygon runs
+    // counterclockwise and ears are the corners that turn left
+    let normal = normal.normalize();
+    let helper = if normal.x.abs() < 0.9 { Float3::new(1.0, 0.0, 0.0) } else { Float3::new(0.0, 1.0, 0.0) };
+    let u = helper.cross(&normal).normalize();
+    let v = normal.cross(&u);
+    let points: Vec<Float2> = positions.iter().map(|p| Float2::new(p.dot(&u), p.dot(&v))).collect();
+
+    let mut remaining: Vec<usize> = (0..positions.len()).collect();
+    let mut triangles = Vec::with_capacity(positions.len() - 2);
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let (prev, current, next) = (remaining[(i + count - 1) % count], remaining[i], remaining[(i + 1) % count]);
+            let (a, b, c) = (points[prev], points[current], points[next]);
+            cross(a, b, c) > 0.0 && !remaining.iter()
+                .filter(|&&other| other != prev && other != current && other != next)
+                .any(|&other| contains(a, b, c, points[other]))
+        });
+
+        let Some(i) = ear else {
+            triangles.extend(fan(&remaining));
+            return Some(triangles);
+        };
+        triangles.push([remaining[(i + count - 1) % count], remaining[i], remaining[(i + 1) % count]]);
+        remaining.remove(i);
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    Some(triangles)
+}
+
+// Twice the signed area of the triangle, positive if it runs counterclockwise
+fn cross(a: Float2, b: Float2, c: Float2) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+// Whether p lies inside or on the edge of the counterclockwise triangle abc
+fn contains(a: Float2, b: Float2, c: Float2, p: Float2) -> bool {
+    cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+}
@@ -0,0 +1,71 @@
+use std::io::{BufWriter, Write};
+
+use crate::render::ImageView;
+
+/// Writes frames to an uncompressed YUV4MPEG2 stream, which most video tools
+/// can read directly.
+///
+/// Frames are stored as 8 bit 4:2:0 with BT.601 limited range colours, and
+/// the header says so rather than leaving the range to the reader. Like the
+/// BMP writer, the last row of the image comes first.
+pub struct Y4mWriter {
+    writer: BufWriter<std::fs::File>,
+    width: usize,
+    height: usize,
+}
+
+impl Y4mWriter {
+    /// Create the file and write the stream header.
+    pub fn new(file_path: &str, width: usize, height: usize, fps: u32) -> std::io::Result<Self> {
+        if let Some(dir) = std::path::Path::new(file_path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(std::fs::File::create(file_path)?);
+        writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg XCOLORRANGE=LIMITED", width, height, fps)?;
+        Ok(Y4mWriter { writer, width, height })
+    }
+
+    pub fn write_frame(&mut self, image: &ImageView) -> std::io::Result<()> {
+        assert!(image.width == self.width && image.height == self.height, "Frame size does not match the stream");
+        let rows: Vec<&[[u8; 4]]> = image.rows().collect();
+        let rgb = |x: usize, y: usize| {
+            let [r, g, b, _] = rows[self.height - 1 - y][x];
+            (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+        };
+
+        self.writer.write_all(b"FRAME\n")?;
+        let mut luma = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (r, g, b) = rgb(x, y);
+                luma.push((16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8);
+            }
+        }
+        self.writer.write_all(&luma)?;
+
+        // Chroma is the average of each 2x2 block, which may be cut off at odd sizes
+        let (chroma_width, chroma_height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let mut cb = Vec::with_capacity(chroma_width * chroma_height);
+        let mut cr = Vec::with_capacity(chroma_width * chroma_height);
+        for y in 0..chroma_height {
+            for x in 0..chroma_width {
+                let (mut r, mut g, mut b, mut count) = (0.0, 0.0, 0.0, 0.0);
+                for (px, py) in [(2 * x, 2 * y), (2 * x + 1, 2 * y), (2 * x, 2 * y + 1), (2 * x + 1, 2 * y + 1)] {
+                    if px < self.width && py < self.height {
+                        let (pr, pg, pb) = rgb(px, py);
+                        (r, g, b, count) = (r + pr, g + pg, b + pb, count + 1.0);
+                    }
+                }
+                let (r, g, b) = (r / count, g / count, b / count);
+                cb.push((128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8);
+                cr.push((128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8);
+            }
+        }
+        self.writer.write_all(&cb)?;
+        self.writer.write_all(&cr)
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}